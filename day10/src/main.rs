@@ -1,31 +1,51 @@
 use std::char;
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
 use std::io;
 use std::iter::FromIterator;
 use std::str;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum Dir {
     Up,
     Down,
     Left,
     Right,
 }
+
+impl Dir {
+    fn opposite(&self) -> Dir {
+        match self {
+            Dir::Up => Dir::Down,
+            Dir::Down => Dir::Up,
+            Dir::Left => Dir::Right,
+            Dir::Right => Dir::Left,
+        }
+    }
+}
 type Coord = (usize, usize);
 type Grid = Vec<Vec<char>>;
 type Pipes = HashMap<Coord, [Dir; 2]>;
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Cell {
+    Loop,
+    Inside,
+    Outside,
+}
+
+#[cfg(test)]
 fn adj_coords(c: &Coord, x_max: usize, y_max: usize) -> Vec<Coord> {
     let &(i, j) = c;
     let mut adj: Vec<Coord> = Vec::with_capacity(4);
-    if i > 1 {
+    if i > 0 {
         adj.push((i - 1, j));
     }
     if i + 1 < x_max {
         adj.push((i + 1, j));
     }
-    if j > 1 {
+    if j > 0 {
         adj.push((i, j - 1));
     }
     if j + 1 < y_max {
@@ -54,11 +74,25 @@ fn connects(dir: Dir, c: &char) -> bool {
     }
 }
 
-fn parse_input(s: &str) -> Option<(Grid, Pipes, Coord)> {
+// the neighbouring coordinate in a direction, if it isn't off the top or left
+fn step(c: &Coord, dir: &Dir) -> Option<Coord> {
+    let &(i, j) = c;
+    match dir {
+        Dir::Up => Some((i.checked_sub(1)?, j)),
+        Dir::Down => Some((i + 1, j)),
+        Dir::Left => Some((i, j.checked_sub(1)?)),
+        Dir::Right => Some((i, j + 1)),
+    }
+}
+
+// The start tile in the grid is replaced by the pipe it must be, which is
+// the one joining the two neighbours that connect back to it.
+fn parse_input(s: &str) -> Result<(Grid, Pipes, Coord), String> {
     let mut adj: Pipes = HashMap::new();
-    let grid: Grid = s.lines().map(|l| l.chars().collect()).collect();
-    let x_max = grid.len();
-    let y_max = grid[0].len();
+    let mut grid: Grid = s.lines().map(|l| l.chars().collect()).collect();
+    if grid.iter().any(|r| r.len() != grid[0].len()) {
+        return Err("rows of different lengths".to_string());
+    }
     let mut start_coord = None;
 
     for (i, row) in grid.iter().enumerate() {
@@ -68,85 +102,111 @@ fn parse_input(s: &str) -> Option<(Grid, Pipes, Coord)> {
                 None => None,
             };
             if *chr == 'S' {
+                if start_coord.is_some() {
+                    return Err("more than one start tile".to_string());
+                }
                 start_coord = Some((i, j));
             }
         }
     }
 
-    let (i, j) = start_coord?;
-    let start_char = if i > 0
-        && connects(Dir::Down, &grid[i - 1][j])
-        && i + 1 < x_max
-        && connects(Dir::Up, &grid[i + 1][j])
-    {
-        '|' // UD
-    } else if i > 0
-        && connects(Dir::Down, &grid[i - 1][j])
-        && j > 0
-        && connects(Dir::Right, &grid[i][j - 1])
-    {
-        'J' // UL
-    } else if i > 0
-        && connects(Dir::Down, &grid[i - 1][j])
-        && j + 1 < y_max
-        && connects(Dir::Left, &grid[i][j + 1])
-    {
-        'L' // UR
-    } else if i + 1 < x_max
-        && connects(Dir::Up, &grid[i + 1][j])
-        && j > 0
-        && connects(Dir::Right, &grid[i][j - 1])
-    {
-        '7' // DL
-    } else if i + 1 < x_max
-        && connects(Dir::Up, &grid[i + 1][j])
-        && j + 1 < y_max
-        && connects(Dir::Left, &grid[i][j + 1])
-    {
-        'F' // DR
-    } else {
-        '-' // LR
-    };
+    let start = start_coord.ok_or("no start tile")?;
+    let dirs: Vec<Dir> = [Dir::Up, Dir::Down, Dir::Left, Dir::Right]
+        .into_iter()
+        .filter(|dir| {
+            step(&start, dir)
+                .and_then(|(i, j)| grid.get(i)?.get(j))
+                .is_some_and(|c| connects(dir.opposite(), c))
+        })
+        .collect();
+    if dirs.len() != 2 {
+        return Err(format!(
+            "start tile {:?} connects to {} pipes, not 2",
+            start,
+            dirs.len()
+        ));
+    }
+    let start_char = "|-LJ7F"
+        .chars()
+        .find(|c| dirs.iter().all(|&dir| connects(dir, c)))
+        .unwrap();
 
-    adj.insert((i, j), char_dirs(&start_char).unwrap());
-    Some((grid, adj, (i, j)))
+    adj.insert(start, char_dirs(&start_char).unwrap());
+    grid[start.0][start.1] = start_char;
+    Ok((grid, adj, start))
 }
 
-fn run_loop(pipes: &Pipes, start: &Coord) -> Vec<Coord> {
+// Follow the pipes from start until they come back to it. On failure, also
+// give the tiles walked, none of which can be on a loop. Reaching a tile in
+// `dead` fails too, as it's already known not to be on one.
+fn walk(
+    pipes: &Pipes,
+    start: &Coord,
+    dead: &HashSet<Coord>,
+) -> Result<Vec<Coord>, (String, Vec<Coord>)> {
     let mut path: Vec<Coord> = vec![*start];
-    let max = pipes.keys().len();
-    for i in 0..=max {
-        let last_state = if i > 0 {
-            path[i - 1]
-        } else {
-            *start //dummy val
+    let Some(dirs) = pipes.get(start) else {
+        return Err((format!("no pipe at {:?}", start), path));
+    };
+    let mut dir = dirs[0];
+    loop {
+        let cur = path[path.len() - 1];
+        let Some((next, next_dirs)) = step(&cur, &dir)
+            .and_then(|next| Some((next, pipes.get(&next)?)))
+            .filter(|(_, d)| d.contains(&dir.opposite()))
+        else {
+            return Err((format!("dead end at {:?} going {:?}", cur, dir), path));
         };
-        let cur_state = path[i];
-        let (i, j) = cur_state;
-
-        let next_state = pipes
-            .get(&cur_state)
-            .unwrap()
-            .into_iter()
-            .map(|dir| match dir {
-                Dir::Up => (i - 1, j),
-                Dir::Down => (i + 1, j),
-                Dir::Left => (i, j - 1),
-                Dir::Right => (i, j + 1),
-            })
-            .filter(|&s| s != last_state)
-            .next()
-            .unwrap();
-        if next_state == *start {
-            break;
-        } else {
-            path.push(next_state);
-        }
-    }
-    path
+        if next == *start {
+            return Ok(path);
+        }
+        if dead.contains(&next) {
+            return Err((format!("{:?} leads to a dead end", start), path));
+        }
+        // there's no way back to start if the path has already used every pipe
+        if path.len() == pipes.len() {
+            return Err((format!("no loop through {:?}", start), path));
+        }
+        path.push(next);
+        dir = *next_dirs.iter().find(|&&d| d != dir.opposite()).unwrap();
+    }
+}
+
+fn run_loop(pipes: &Pipes, start: &Coord) -> Result<Vec<Coord>, String> {
+    walk(pipes, start, &HashSet::new()).map_err(|(e, _)| e)
+}
+
+// Every closed loop of pipes in the grid, each from its top-left tile.
+fn all_loops(pipes: &Pipes) -> Vec<Vec<Coord>> {
+    let mut coords: Vec<&Coord> = pipes.keys().collect();
+    coords.sort();
+    let mut seen: HashSet<Coord> = HashSet::new();
+    let mut loops = Vec::new();
+    for c in coords {
+        if seen.contains(c) {
+            continue;
+        }
+        // A path that fails can't contain a loop tile either, as the tiles
+        // on a loop only connect to each other. Failed walks stop at the
+        // tiles already seen, so each tile is walked once.
+        match walk(pipes, c, &seen) {
+            Ok(path) => {
+                seen.extend(path.iter().copied());
+                loops.push(path);
+            }
+            Err((_, path)) => {
+                for t in path {
+                    let fresh = seen.insert(t);
+                    debug_assert!(fresh, "{:?} walked twice", t);
+                }
+            }
+        }
+    }
+    loops
 }
 
 // find if coordinate is inside the loop using number of ray crossings
+#[cfg(test)]
 fn is_inside_loop(c: &Coord, loop_set: &HashSet<Coord>, grid: &Grid) -> bool {
     let mut crosses = 0;
     let &(i, j) = c;
@@ -162,13 +222,13 @@ fn is_inside_loop(c: &Coord, loop_set: &HashSet<Coord>, grid: &Grid) -> bool {
     crosses % 2 == 1
 }
 
+#[cfg(test)]
 fn flood_fill(c: &Coord, loop_set: &HashSet<Coord>, grid: &Grid) -> HashSet<Coord> {
     let x_max = grid.len();
     let y_max = grid[0].len();
     let mut edge: Vec<Coord> = vec![*c];
     let mut filled: HashSet<Coord> = HashSet::new();
-    while !edge.is_empty() {
-        let expand = edge.pop().unwrap();
+    while let Some(expand) = edge.pop() {
         filled.insert(expand);
 
         let adj_coords = adj_coords(&expand, x_max, y_max);
@@ -181,7 +241,60 @@ fn flood_fill(c: &Coord, loop_set: &HashSet<Coord>, grid: &Grid) -> HashSet<Coor
     filled
 }
 
+// Classify every cell by scanning each row and counting crossings of the
+// loop. Only loop tiles with a pipe going up are crossings: a run like L--7
+// crosses once, while L--J turns back and crosses twice.
+fn classify(loop_set: &HashSet<Coord>, grid: &Grid) -> Vec<Vec<Cell>> {
+    grid.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut inside = false;
+            row.iter()
+                .enumerate()
+                .map(|(j, c)| {
+                    if !loop_set.contains(&(i, j)) {
+                        if inside {
+                            Cell::Inside
+                        } else {
+                            Cell::Outside
+                        }
+                    } else {
+                        if connects(Dir::Up, c) {
+                            inside = !inside;
+                        }
+                        Cell::Loop
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Count the cells inside the loop from its area (shoelace formula) and
+// length, with Pick's theorem.
+#[cfg(test)]
+fn shoelace_interior(loop_vec: &[Coord]) -> usize {
+    let n = loop_vec.len();
+    let area2: i64 = (0..n)
+        .map(|k| {
+            let (x1, y1) = loop_vec[k];
+            let (x2, y2) = loop_vec[(k + 1) % n];
+            x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64
+        })
+        .sum();
+    ((area2.unsigned_abs() as usize + 2).saturating_sub(n)) / 2
+}
+
 fn solve2(loop_set: &HashSet<Coord>, grid: &Grid) -> usize {
+    classify(loop_set, grid)
+        .iter()
+        .flatten()
+        .filter(|&&c| c == Cell::Inside)
+        .count()
+}
+
+#[cfg(test)]
+fn solve2_flood_fill(loop_set: &HashSet<Coord>, grid: &Grid) -> usize {
     let mut interior_points = 0;
     let mut filled: HashSet<Coord> = HashSet::new();
 
@@ -204,16 +317,131 @@ fn solve2(loop_set: &HashSet<Coord>, grid: &Grid) -> usize {
     interior_points
 }
 
+// the box-drawing character for a pipe
+fn box_char(c: char) -> char {
+    match c {
+        '|' => '│',
+        '-' => '─',
+        'F' => '┌',
+        '7' => '┐',
+        'L' => '└',
+        'J' => '┘',
+        _ => c,
+    }
+}
+
+// Draw the grid for a terminal: the loop in bold, other pipes dimmed and
+// the cells inside the loop as green dots.
+fn render(grid: &Grid, cells: &[Vec<Cell>]) -> String {
+    let mut out = String::new();
+    for (row, cells) in grid.iter().zip(cells) {
+        for (&c, cell) in row.iter().zip(cells) {
+            match cell {
+                Cell::Loop => out += &format!("\x1b[1m{}\x1b[0m", box_char(c)),
+                Cell::Inside => out += "\x1b[32m•\x1b[0m",
+                Cell::Outside if char_dirs(&c).is_some() => {
+                    out += &format!("\x1b[2m{}\x1b[0m", box_char(c))
+                }
+                Cell::Outside => out.push(' '),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Draw the grid as a binary PPM image, each tile 3x3 pixels with the pipe
+// running through the middle, so the gaps between pipes show up.
+fn render_ppm(grid: &Grid, cells: &[Vec<Cell>]) -> Vec<u8> {
+    const LOOP: [u8; 3] = [255, 255, 255];
+    const JUNK: [u8; 3] = [90, 90, 90];
+    const INSIDE: [u8; 3] = [40, 160, 60];
+    const OUTSIDE: [u8; 3] = [0, 0, 0];
+
+    let height = grid.len() * 3;
+    let width = grid.first().map_or(0, |r| r.len()) * 3;
+    let mut pixels = vec![OUTSIDE; width * height];
+    for (i, (row, cells)) in grid.iter().zip(cells).enumerate() {
+        for (j, (c, cell)) in row.iter().zip(cells).enumerate() {
+            let mut set = |di: usize, dj: usize, color: [u8; 3]| {
+                pixels[(3 * i + di) * width + 3 * j + dj] = color;
+            };
+            if *cell == Cell::Inside {
+                for k in 0..9 {
+                    set(k / 3, k % 3, INSIDE);
+                }
+            }
+            let Some(dirs) = char_dirs(c) else {
+                continue;
+            };
+            let color = if *cell == Cell::Loop { LOOP } else { JUNK };
+            set(1, 1, color);
+            for dir in dirs.iter() {
+                match dir {
+                    Dir::Up => set(0, 1, color),
+                    Dir::Down => set(2, 1, color),
+                    Dir::Left => set(1, 0, color),
+                    Dir::Right => set(1, 2, color),
+                }
+            }
+        }
+    }
+
+    let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    out.extend(pixels.into_iter().flatten());
+    out
+}
+
 fn main() -> io::Result<()> {
     let input = fs::read_to_string("input.txt")?;
-    let (grid, pipes, start) = parse_input(&input).unwrap();
-    let loop_vec = run_loop(&pipes, &start);
-    let ans1 = (loop_vec.len() + 1) / 2;
-    println!("{}", ans1);
+    let to_io_err = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let (grid, pipes, start) = parse_input(&input).map_err(to_io_err)?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let [flag] = args.as_slice() {
+        if flag == "--loops" {
+            // the start and length of every closed loop
+            for path in all_loops(&pipes) {
+                println!("{:?} {}", path[0], path.len());
+            }
+            return Ok(());
+        }
+    }
 
+    let loop_vec = run_loop(&pipes, &start).map_err(to_io_err)?;
+    let ans1 = loop_vec.len().div_ceil(2);
     let loop_set: HashSet<Coord> = HashSet::from_iter(loop_vec);
-    let ans2 = solve2(&loop_set, &grid);
-    println!("{}", ans2);
+    match args.as_slice() {
+        [] => {
+            println!("{}", ans1);
+            println!("{}", solve2(&loop_set, &grid));
+        }
+        // I and O for the cells inside and outside the loop
+        [flag] if flag == "--classify" => {
+            for (row, cells) in grid.iter().zip(classify(&loop_set, &grid)) {
+                let line: String = row
+                    .iter()
+                    .zip(cells)
+                    .map(|(&c, cell)| match cell {
+                        Cell::Loop => c,
+                        Cell::Inside => 'I',
+                        Cell::Outside => 'O',
+                    })
+                    .collect();
+                println!("{}", line);
+            }
+        }
+        [flag] if flag == "--render" => {
+            print!("{}", render(&grid, &classify(&loop_set, &grid)));
+        }
+        [flag, path] if flag == "--ppm" => {
+            fs::write(path, render_ppm(&grid, &classify(&loop_set, &grid)))?;
+        }
+        _ => {
+            let usage = "usage: day10 [--loops | --classify | --render | --ppm <file>]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
@@ -222,7 +450,7 @@ fn main() -> io::Result<()> {
 fn test_grid_2() {
     let input = fs::read_to_string("test_2.txt").unwrap();
     let (grid, pipes, start) = parse_input(&input).unwrap();
-    let loop_vec = run_loop(&pipes, &start);
+    let loop_vec = run_loop(&pipes, &start).unwrap();
     let loop_set: HashSet<Coord> = HashSet::from_iter(loop_vec);
 
     let coord = (4, 10);
@@ -234,3 +462,91 @@ fn test_grid_2() {
     let interior_count = solve2(&loop_set, &grid);
     assert_eq!(interior_count, 10);
 }
+
+#[test]
+fn test_interior() {
+    for file in ["test.txt", "test_2.txt"] {
+        let input = fs::read_to_string(file).unwrap();
+        let (grid, pipes, start) = parse_input(&input).unwrap();
+        let loop_vec = run_loop(&pipes, &start).unwrap();
+        let loop_set: HashSet<Coord> = HashSet::from_iter(loop_vec.iter().copied());
+
+        let expected = solve2_flood_fill(&loop_set, &grid);
+        assert_eq!(solve2(&loop_set, &grid), expected);
+        assert_eq!(shoelace_interior(&loop_vec), expected);
+
+        // the cells inside are exactly those the flood fill finds inside
+        let cells = classify(&loop_set, &grid);
+        for (i, row) in cells.iter().enumerate() {
+            for (j, &cell) in row.iter().enumerate() {
+                let c = (i, j);
+                let expected = if loop_set.contains(&c) {
+                    Cell::Loop
+                } else if is_inside_loop(&c, &loop_set, &grid) {
+                    Cell::Inside
+                } else {
+                    Cell::Outside
+                };
+                assert_eq!(cell, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_loops() {
+    assert!(parse_input(".|.\n.|.").is_err());
+    assert!(parse_input("S-S\n...").is_err());
+    // no pipes lead into start, or three do
+    assert!(parse_input("S.\n..").is_err());
+    assert!(parse_input(".|.\n-S-\n...").is_err());
+
+    let (_, pipes, start) = parse_input("S-7\n|.|\nL--").unwrap();
+    assert!(run_loop(&pipes, &start)
+        .unwrap_err()
+        .starts_with("dead end"));
+
+    // two loops and a chain of junk, each tile of which all_loops walks only
+    // once, as it asserts
+    let (_, pipes, start) = parse_input("S7F7\nLJLJ\n----").unwrap();
+    assert_eq!(run_loop(&pipes, &start).unwrap().len(), 4);
+    let loops = all_loops(&pipes);
+    assert_eq!(loops.len(), 2);
+    assert!(loops.iter().all(|l| l.len() == 4));
+    let (_, walked) = walk(&pipes, &(2, 3), &HashSet::new()).unwrap_err();
+    assert_eq!(walked, [(2, 3), (2, 2), (2, 1), (2, 0)]);
+    let dead = HashSet::from([(2, 1)]);
+    let (_, walked) = walk(&pipes, &(2, 3), &dead).unwrap_err();
+    assert_eq!(walked, [(2, 3), (2, 2)]);
+
+    // a long chain takes linear time
+    let n = 20000;
+    let input = format!("S7{}\nLJ{}", "-".repeat(n), ".".repeat(n));
+    let (_, pipes, _) = parse_input(&input).unwrap();
+    assert_eq!(all_loops(&pipes).len(), 1);
+}
+
+#[test]
+fn test_render() {
+    let input = fs::read_to_string("test.txt").unwrap();
+    let (grid, pipes, start) = parse_input(&input).unwrap();
+    let loop_set: HashSet<Coord> = HashSet::from_iter(run_loop(&pipes, &start).unwrap());
+    let cells = classify(&loop_set, &grid);
+
+    let ppm = render_ppm(&grid, &cells);
+    let header = "P6\n15 15\n255\n";
+    assert!(ppm.starts_with(header.as_bytes()));
+    assert_eq!(ppm.len(), header.len() + 15 * 15 * 3);
+
+    // the third row, with the start drawn as the pipe it stands for and the
+    // one tile inside the loop
+    let text = render(&grid, &cells);
+    let row = text.lines().nth(2).unwrap();
+    let plain: String = row
+        .split('\x1b')
+        .map(|s| s.split_once('m').map_or(s, |(_, t)| t))
+        .collect();
+    assert_eq!(plain, "┌┘•└┐");
+
+    assert!(parse_input("S7\nLJ\n.").is_err());
+}
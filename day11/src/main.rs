@@ -1,21 +1,23 @@
+use std::collections::BinaryHeap;
+use std::env;
 use std::fs;
 use std::io;
 
 type Grid = Vec<Vec<char>>;
 type Coord = (usize, usize);
+// coordinates after expansion, which can be far beyond the grid
+type Point = (u64, u64);
 
 fn parse_grid(s: &str) -> (Grid, Vec<Coord>) {
     let grid: Grid = s
         .lines()
         .map(|l| l.chars().collect::<Vec<char>>())
         .collect();
-    let n = grid.len();
-    let m = grid[0].len();
 
     let mut planets: Vec<Coord> = Vec::new();
-    for i in 0..n {
-        for j in 0..m {
-            if grid[i][j] == '#' {
+    for (i, row) in grid.iter().enumerate() {
+        for (j, &c) in row.iter().enumerate() {
+            if c == '#' {
                 planets.push((i, j));
             }
         }
@@ -23,86 +25,247 @@ fn parse_grid(s: &str) -> (Grid, Vec<Coord>) {
     (grid, planets)
 }
 
-// coords of planets when empty rows and cols expand by factor
-fn expand(
-    planets: &Vec<Coord>,
-    empty_rows: &Vec<usize>,
-    empty_cols: &Vec<usize>,
-    factor: usize,
-) -> Vec<Coord> {
-    let mut expanded = planets.clone();
-    for (i, r) in empty_rows.iter().enumerate() {
-        for p in expanded.iter_mut() {
-            if p.0 > *r + i * (factor - 1) {
-                p.0 += factor - 1;
-            }
-        }
-    }
-    for (i, c) in empty_cols.iter().enumerate() {
-        for p in expanded.iter_mut() {
-            if p.1 > *c + i * (factor - 1) {
-                p.1 += factor - 1;
-            }
+fn empty_lines(grid: &Grid) -> (Vec<usize>, Vec<usize>) {
+    let m = grid.first().map_or(0, |r| r.len());
+    let empty_rows: Vec<usize> = (0..grid.len())
+        .filter(|&i| grid[i].iter().all(|&c| c == '.'))
+        .collect();
+    let empty_cols: Vec<usize> = (0..m)
+        .filter(|&j| grid.iter().all(|r| r[j] == '.'))
+        .collect();
+    (empty_rows, empty_cols)
+}
+
+// Where each index lands when every empty line becomes `factor` lines. An
+// index moves by the number of empty lines before it.
+fn expand_axis(len: usize, empty: &[usize], factor: u64) -> Vec<u64> {
+    let mut moved = Vec::with_capacity(len);
+    let mut before = 0;
+    for i in 0..len {
+        moved.push(i as u64 - before + before * factor);
+        if empty.binary_search(&i).is_ok() {
+            before += 1;
         }
     }
-    expanded
+    moved
 }
 
-fn distances(planets: &Vec<Coord>) -> Vec<usize> {
-    let n = planets.len();
-    let mut dists: Vec<usize> = Vec::new();
-    for i in 0..n {
-        for j in i..n {
-            let (x1, y1) = planets[i];
-            let (x2, y2) = planets[j];
-            let xdiff = if x1 < x2 { x2 - x1 } else { x1 - x2 };
-            let ydiff = if y1 < y2 { y2 - y1 } else { y1 - y2 };
-            dists.push(xdiff + ydiff);
-        }
+// coords of planets when empty rows and cols expand by their factors
+fn expand(
+    grid: &Grid,
+    planets: &[Coord],
+    empty_rows: &[usize],
+    empty_cols: &[usize],
+    factors: (u64, u64),
+) -> Vec<Point> {
+    let m = grid.first().map_or(0, |r| r.len());
+    let rows = expand_axis(grid.len(), empty_rows, factors.0);
+    let cols = expand_axis(m, empty_cols, factors.1);
+    planets.iter().map(|&(i, j)| (rows[i], cols[j])).collect()
+}
+
+// The sum of |a - b| over all pairs. Once sorted, each value is the larger
+// of the pair with everything before it.
+fn pair_sum(mut values: Vec<u64>) -> u128 {
+    values.sort_unstable();
+    let mut sum: u128 = 0;
+    let mut prefix: u128 = 0;
+    for (i, &v) in values.iter().enumerate() {
+        sum += v as u128 * i as u128 - prefix;
+        prefix += v as u128;
     }
-    dists
+    sum
 }
 
-fn solve(grid: &Grid, planets: &Vec<Coord>, factor: usize) -> usize {
-    let empty_rows: Vec<usize> = grid
-        .iter()
-        .enumerate()
-        .filter_map(|(i, r)| {
-            if r.iter().all(|&c| c == '.') {
-                Some(i)
-            } else {
-                None
-            }
-        })
-        .collect();
+// the total distance between every pair of distinct planets
+fn distance_sum(planets: &[Point]) -> u128 {
+    pair_sum(planets.iter().map(|p| p.0).collect())
+        + pair_sum(planets.iter().map(|p| p.1).collect())
+}
+
+// The galaxies after expansion, numbered from 1 in reading order.
+struct Universe {
+    galaxies: Vec<Point>,
+}
+
+impl Universe {
+    fn new(grid: &Grid, planets: &[Coord], factors: (u64, u64)) -> Universe {
+        let (empty_rows, empty_cols) = empty_lines(grid);
+        let galaxies = expand(grid, planets, &empty_rows, &empty_cols, factors);
+        Universe { galaxies }
+    }
+
+    fn get(&self, id: usize) -> Result<Point, String> {
+        id.checked_sub(1)
+            .and_then(|i| self.galaxies.get(i).copied())
+            .ok_or(format!("no galaxy {}", id))
+    }
 
-    let mut empty_cols: Vec<usize> = Vec::new();
-    let n = grid.len();
-    let m = grid[0].len();
-    for j in 0..m {
-        let mut empty = true;
+    fn dist(a: Point, b: Point) -> u64 {
+        a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+    }
+
+    fn distance(&self, a: usize, b: usize) -> Result<u64, String> {
+        Ok(Universe::dist(self.get(a)?, self.get(b)?))
+    }
+
+    fn total(&self) -> u128 {
+        distance_sum(&self.galaxies)
+    }
+
+    // each galaxy's nearest other galaxy and the distance to it, taking the
+    // lowest id on a tie
+    fn nearest(&self) -> Vec<Option<(usize, u64)>> {
+        let n = self.galaxies.len();
+        (0..n)
+            .map(|i| {
+                (0..n)
+                    .filter(|&j| j != i)
+                    .map(|j| (Universe::dist(self.galaxies[i], self.galaxies[j]), j + 1))
+                    .min()
+                    .map(|(d, id)| (id, d))
+            })
+            .collect()
+    }
+
+    // The k closest pairs as (id, id, distance), closest first. A heap of
+    // the best k so far keeps this to O(n^2 log k).
+    fn closest_pairs(&self, k: usize) -> Vec<(usize, usize, u64)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut heap: BinaryHeap<(u64, usize, usize)> = BinaryHeap::with_capacity(k + 1);
+        let n = self.galaxies.len();
         for i in 0..n {
-            if grid[i][j] != '.' {
-                empty = false;
-                break;
+            for j in (i + 1)..n {
+                let pair = (
+                    Universe::dist(self.galaxies[i], self.galaxies[j]),
+                    i + 1,
+                    j + 1,
+                );
+                if heap.len() < k {
+                    heap.push(pair);
+                } else if heap.peek().is_some_and(|&worst| pair < worst) {
+                    heap.pop();
+                    heap.push(pair);
+                }
             }
         }
-        if empty {
-            empty_cols.push(j);
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(d, a, b)| (a, b, d))
+            .collect()
+    }
+
+    // the distance between every pair, with the ids along the first row and
+    // column
+    fn to_csv(&self) -> String {
+        let n = self.galaxies.len();
+        let mut csv = String::new();
+        let ids: Vec<String> = (1..=n).map(|id| id.to_string()).collect();
+        csv.push_str(&format!(",{}\n", ids.join(",")));
+        for (i, &a) in self.galaxies.iter().enumerate() {
+            let row: Vec<String> = self
+                .galaxies
+                .iter()
+                .map(|&b| Universe::dist(a, b).to_string())
+                .collect();
+            csv.push_str(&format!("{},{}\n", i + 1, row.join(",")));
         }
+        csv
     }
+}
 
-    let expanded = expand(&planets, &empty_rows, &empty_cols, factor);
-    let dist = distances(&expanded);
-    dist.into_iter().sum()
+fn solve(grid: &Grid, planets: &[Coord], factors: (u64, u64)) -> u128 {
+    Universe::new(grid, planets, factors).total()
 }
 
 fn main() -> io::Result<()> {
     let input = fs::read_to_string("input.txt")?;
     let (grid, planets) = parse_grid(&input);
 
-    println!("{}", solve(&grid, &planets, 2));
-    println!("{}", solve(&grid, &planets, 1000000));
+    let bad_arg =
+        |a: &String| io::Error::new(io::ErrorKind::InvalidInput, format!("bad argument {}", a));
+    let parse = |a: &String| a.parse::<u64>().map_err(|_| bad_arg(a));
+    let parse_id = |a: &String| a.parse::<usize>().map_err(|_| bad_arg(a));
+
+    // day11 <row factor> <col factor> [query]
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        println!("{}", solve(&grid, &planets, (2, 2)));
+        println!("{}", solve(&grid, &planets, (1000000, 1000000)));
+        return Ok(());
+    }
+    let usage = "usage: day11 [<row factor> <col factor> \
+                 [--distance <id> <id> | --nearest | --closest <k> | --csv]]";
+    let (factors, query) = match args.as_slice() {
+        [rows, cols, query @ ..] => ((parse(rows)?, parse(cols)?), query),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, usage)),
+    };
+    let universe = Universe::new(&grid, &planets, factors);
+    match query {
+        [] => println!("{}", universe.total()),
+        [flag, a, b] if flag == "--distance" => {
+            let d = universe
+                .distance(parse_id(a)?, parse_id(b)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            println!("{}", d);
+        }
+        [flag] if flag == "--nearest" => {
+            for (i, nearest) in universe.nearest().iter().enumerate() {
+                match nearest {
+                    Some((id, d)) => println!("{} {} {}", i + 1, id, d),
+                    None => println!("{} none", i + 1),
+                }
+            }
+        }
+        [flag, k] if flag == "--closest" => {
+            for (a, b, d) in universe.closest_pairs(parse_id(k)?) {
+                println!("{} {} {}", a, b, d);
+            }
+        }
+        [flag] if flag == "--csv" => print!("{}", universe.to_csv()),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, usage)),
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let input = fs::read_to_string("test.txt").unwrap();
+    let (grid, planets) = parse_grid(&input);
+    assert_eq!(solve(&grid, &planets, (2, 2)), 374);
+    assert_eq!(solve(&grid, &planets, (10, 10)), 1030);
+    assert_eq!(solve(&grid, &planets, (100, 100)), 8410);
+
+    // each empty line counts once per factor, rows and columns separately
+    let base = solve(&grid, &planets, (1, 1));
+    let per_row = solve(&grid, &planets, (2, 1)) - base;
+    let per_col = solve(&grid, &planets, (1, 2)) - base;
+    let big = 10_u64.pow(12);
+    assert_eq!(
+        solve(&grid, &planets, (big, big + 1)),
+        base + per_row * (big as u128 - 1) + per_col * big as u128
+    );
+
+    let universe = Universe::new(&grid, &planets, (2, 2));
+    assert_eq!(universe.distance(5, 9), Ok(9));
+    assert_eq!(universe.distance(1, 7), Ok(15));
+    assert_eq!(universe.distance(3, 6), Ok(17));
+    assert_eq!(universe.distance(8, 9), Ok(5));
+    assert!(universe.distance(0, 1).is_err());
+    assert!(universe.distance(1, 10).is_err());
+    assert_eq!(universe.nearest()[7], Some((9, 5)));
+
+    // every pair, closest first, adds up to the total
+    let pairs = universe.closest_pairs(100);
+    assert_eq!(pairs.len(), 36);
+    assert!(pairs.windows(2).all(|w| w[0].2 <= w[1].2));
+    assert_eq!(pairs.iter().map(|p| p.2 as u128).sum::<u128>(), 374);
+    assert_eq!(universe.closest_pairs(3), pairs[..3]);
+
+    let csv = universe.to_csv();
+    assert_eq!(csv.lines().count(), 10);
+    assert_eq!(csv.lines().nth(5).unwrap().split(',').nth(9), Some("9"));
+}
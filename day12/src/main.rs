@@ -1,7 +1,10 @@
+use std::env;
 use std::fs;
 use std::io;
 use std::iter;
 
+mod nonogram;
+
 type Row = (Vec<char>, Vec<usize>);
 
 fn parse_row(s: &str) -> Option<Row> {
@@ -14,81 +17,335 @@ fn parse_row(s: &str) -> Option<Row> {
     Some((springs, groups))
 }
 
-fn is_placeable(spring: &Vec<char>, i: usize, group: usize) -> bool {
-    (i + group - 1 < spring.len())
-        && (i + group == spring.len() || spring[i + group] != '#')
-        && spring[i..i + group].iter().all(|c| *c != '.')
+// dots[i] = # springs before i known to be operational
+fn dot_counts(springs: &[char]) -> Vec<usize> {
+    iter::once(0)
+        .chain(springs.iter().scan(0, |n, &c| {
+            *n += (c == '.') as usize;
+            Some(*n)
+        }))
+        .collect()
+}
+
+// whether a group can start at i, with no operational spring in it and no
+// damaged one right after it
+fn is_placeable(springs: &[char], dots: &[usize], i: usize, group: usize) -> bool {
+    i + group <= springs.len()
+        && (i + group == springs.len() || springs[i + group] != '#')
+        && dots[i + group] == dots[i]
 }
 
-fn solve(row: &Row) -> usize {
-    let (springs, group) = row;
+// pre[g][i] = # arrangements of springs[..i] holding groups[..g]
+fn prefix_counts(row: &Row) -> Vec<Vec<u128>> {
+    let (springs, groups) = row;
     let n_spr = springs.len();
-    let n_grp = group.len();
+    let n_grp = groups.len();
+    let dots = dot_counts(springs);
+    let mut pre: Vec<Vec<u128>> = vec![vec![0; n_spr + 1]; n_grp + 1];
+    pre[0][0] = 1;
+    for g in 0..=n_grp {
+        for i in 1..=n_spr {
+            if springs[i - 1] != '#' {
+                pre[g][i] += pre[g][i - 1];
+            }
+            if g == 0 || i < groups[g - 1] {
+                continue;
+            }
+            // the group ends just before i, after a gap unless it starts the row
+            let s = i - groups[g - 1];
+            if dots[i] == dots[s] {
+                pre[g][i] += match s {
+                    0 => pre[g - 1][0],
+                    _ if springs[s - 1] != '#' => pre[g - 1][s - 1],
+                    _ => 0,
+                };
+            }
+        }
+    }
+    pre
+}
 
-    // counts[n][i] = # configurations with n groups, where nth group starts at position i
-    let mut counts: Vec<Vec<usize>> = iter::repeat_with(|| vec![0; n_spr]).take(n_grp).collect();
-    for i in 0..n_spr {
-        if is_placeable(&springs, i, group[0]) {
-            counts[0][i] = 1;
+// ways[g][i] = # arrangements of springs[i..] holding groups[g..]
+fn suffix_counts(row: &Row) -> Vec<Vec<u128>> {
+    let (springs, groups) = row;
+    let n_spr = springs.len();
+    let n_grp = groups.len();
+    let dots = dot_counts(springs);
+    let mut ways: Vec<Vec<u128>> = vec![vec![0; n_spr + 1]; n_grp + 1];
+    ways[n_grp][n_spr] = 1;
+    for g in (0..=n_grp).rev() {
+        for i in (0..n_spr).rev() {
+            if springs[i] != '#' {
+                ways[g][i] += ways[g][i + 1];
+            }
+            if g < n_grp && is_placeable(springs, &dots, i, groups[g]) {
+                let end = i + groups[g];
+                ways[g][i] += ways[g + 1][(end + 1).min(n_spr)];
+            }
         }
-        if springs[i] == '#' {
-            break;
+    }
+    ways
+}
+
+// The number of arrangements. Each count builds on the ones for a spring
+// shorter, so this is O(groups * springs).
+fn solve(row: &Row) -> u128 {
+    prefix_counts(row)[row.1.len()][row.0.len()]
+}
+
+// k copies of the row, with an unknown spring between each
+fn unfold(row: &Row, k: usize) -> Row {
+    let (springs, groups) = row;
+    let mut unfolded: Vec<char> = Vec::with_capacity(k * (springs.len() + 1));
+    for i in 0..k {
+        if i > 0 {
+            unfolded.push('?');
         }
+        unfolded.extend_from_slice(springs);
     }
+    (unfolded, groups.repeat(k))
+}
 
-    for n in 1..n_grp {
-        // find count[n][j] from count[n-1][i]...
-        for i in 0..n_spr {
-            let count = counts[n - 1][i];
-            if count != 0 {
-                for j in (i + group[n - 1] + 1)..n_spr {
-                    if is_placeable(&springs, j, group[n]) {
-                        counts[n][j] += count;
-                    }
-                    if springs[j] == '#' {
-                        break; // stop because we reached the nth group
-                    }
+// The kth arrangement, counting from 0, with arrangements ordered as strings
+// so '#' comes before '.'. At each spring, the arrangements that start a group
+// there come first, and the counts say how many of them there are.
+fn kth_with(row: &Row, ways: &[Vec<u128>], mut k: u128) -> Option<String> {
+    let (springs, groups) = row;
+    let dots = dot_counts(springs);
+    if k >= ways[0][0] {
+        return None;
+    }
+    let mut arrangement = String::with_capacity(springs.len());
+    let (mut g, mut i) = (0, 0);
+    while i < springs.len() {
+        if g < groups.len() && is_placeable(springs, &dots, i, groups[g]) {
+            let end = i + groups[g];
+            let placed = ways[g + 1][(end + 1).min(springs.len())];
+            if k < placed {
+                arrangement.extend(iter::repeat_n('#', groups[g]));
+                if end < springs.len() {
+                    arrangement.push('.');
                 }
+                g += 1;
+                i = end + 1;
+                continue;
             }
+            k -= placed;
         }
+        arrangement.push('.');
+        i += 1;
     }
+    Some(arrangement)
+}
+
+fn kth_arrangement(row: &Row, k: u128) -> Option<String> {
+    kth_with(row, &suffix_counts(row), k)
+}
+
+// every arrangement in order, each built only when it's asked for
+fn arrangements(row: &Row) -> impl Iterator<Item = String> + '_ {
+    let ways = suffix_counts(row);
+    (0..ways[0][0]).filter_map(move |k| kth_with(row, &ways, k))
+}
 
-    // discount ones with more than n groups
-    let n = n_grp - 1;
-    for i in 0..n_spr {
-        let grp = group[n];
-        if i + grp <= n_spr && springs[(i + grp)..].contains(&'#') {
-            counts[n][i] = 0;
+// For every spring, the number of arrangements in which it's damaged. Each
+// placement of a group has the prefix count of arrangements before it times
+// the suffix count after it, and adds that to every spring it covers.
+fn damaged_counts(row: &Row) -> Vec<u128> {
+    let (springs, groups) = row;
+    let n_spr = springs.len();
+    let dots = dot_counts(springs);
+    let pre = prefix_counts(row);
+    let ways = suffix_counts(row);
+    // what each placement adds where it starts and takes away past its end
+    let mut starts: Vec<u128> = vec![0; n_spr + 1];
+    let mut ends: Vec<u128> = vec![0; n_spr + 1];
+    for (g, &len) in groups.iter().enumerate() {
+        for s in 0..n_spr {
+            if !is_placeable(springs, &dots, s, len) {
+                continue;
+            }
+            let before = match s {
+                0 => pre[g][0],
+                _ if springs[s - 1] != '#' => pre[g][s - 1],
+                _ => 0,
+            };
+            let with = before * ways[g + 1][(s + len + 1).min(n_spr)];
+            starts[s] += with;
+            ends[s + len] += with;
         }
     }
-    counts[n].iter().sum()
+    let mut running: u128 = 0;
+    (0..n_spr)
+        .map(|i| {
+            running = running + starts[i] - ends[i];
+            running
+        })
+        .collect()
 }
 
-fn quintuple(row: &Row) -> Row {
-    let mut spring: Vec<char> = row.0.clone();
-    spring.push('?');
-    let slen = spring.len();
-    let spring_5 = spring.into_iter().cycle().take(5 * slen - 1).collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Damaged,
+    Operational,
+    Unknown,
+}
 
-    let groups = &row.1;
-    let groups_5: Vec<usize> = groups
-        .clone()
+// What each spring is in every arrangement, or None if there are none.
+fn deduce(row: &Row) -> Option<Vec<Cell>> {
+    let total = solve(row);
+    if total == 0 {
+        return None;
+    }
+    let cells = damaged_counts(row)
         .into_iter()
-        .cycle()
-        .take(5 * groups.len())
+        .map(|n| match n {
+            0 => Cell::Operational,
+            n if n == total => Cell::Damaged,
+            _ => Cell::Unknown,
+        })
+        .collect();
+    Some(cells)
+}
+
+// List every '?' with how many arrangements make it damaged and whether
+// that's forced.
+fn report_unknowns(row: &Row) {
+    let total = solve(row);
+    let Some(cells) = deduce(row) else {
+        println!("no arrangements");
+        return;
+    };
+    let filled: String = cells
+        .iter()
+        .map(|c| match c {
+            Cell::Damaged => '#',
+            Cell::Operational => '.',
+            Cell::Unknown => '?',
+        })
         .collect();
-    (spring_5, groups_5)
+    println!("{} of {} arrangements", filled, total);
+    let counts = damaged_counts(row);
+    for (i, &c) in row.0.iter().enumerate() {
+        if c == '?' {
+            let forced = match cells[i] {
+                Cell::Damaged => "damaged",
+                Cell::Operational => "operational",
+                Cell::Unknown => "",
+            };
+            println!("{:>4} {:>20} {}", i, counts[i], forced);
+        }
+    }
+}
+
+// xorshift, so sampling needs no dependencies
+fn next_rand(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+// Arrangements drawn uniformly at random with replacement. An index is drawn
+// below the count, rejecting the top of the range that would bias it.
+fn sample(row: &Row, n: usize, seed: u64) -> Vec<String> {
+    let ways = suffix_counts(row);
+    let total = ways[0][0];
+    if total == 0 {
+        return Vec::new();
+    }
+    let mut state = seed.max(1);
+    let limit = u128::MAX - u128::MAX % total;
+    (0..n)
+        .map(|_| loop {
+            let r = (next_rand(&mut state) as u128) << 64 | next_rand(&mut state) as u128;
+            if r < limit {
+                return kth_with(row, &ways, r % total).unwrap();
+            }
+        })
+        .collect()
+}
+
+// Print the solved picture, or say why there isn't one.
+fn solve_nonogram(path: &str) -> io::Result<()> {
+    let puzzle = nonogram::parse_puzzle(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let solutions = puzzle.solve();
+    match solutions.grid {
+        Some(grid) => {
+            print!("{}", nonogram::render(&grid));
+            if !solutions.unique {
+                println!("not unique");
+            }
+        }
+        None => println!("no solution"),
+    }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let [flag, path] = args.as_slice() {
+        if flag == "--nonogram" {
+            return solve_nonogram(path);
+        }
+    }
+
     let input = fs::read_to_string("input.txt")?;
-    let rows: Vec<Row> = input.lines().filter_map(|l| parse_row(l)).collect();
+    let rows: Vec<Row> = input.lines().filter_map(parse_row).collect();
+
+    let bad_arg =
+        |a: &String| io::Error::new(io::ErrorKind::InvalidInput, format!("bad argument {}", a));
+    let parse = |a: &String| a.parse::<u64>().map_err(|_| bad_arg(a));
+    // lines are numbered from 1
+    let get_row = |a: &String| {
+        a.parse::<usize>()
+            .ok()
+            .and_then(|i| rows.get(i.checked_sub(1)?))
+            .ok_or(bad_arg(a))
+    };
 
-    let ans: usize = rows.iter().map(|r| solve(r)).sum();
-    println!("{}", ans);
+    match args.as_slice() {
+        [] => {
+            let ans: u128 = rows.iter().map(solve).sum();
+            println!("{}", ans);
 
-    let ans2: usize = rows.iter().map(|r| solve(&quintuple(r))).sum();
-    println!("{}", ans2);
+            let ans2: u128 = rows.iter().map(|r| solve(&unfold(r, 5))).sum();
+            println!("{}", ans2);
+        }
+        [flag, k] if flag == "--unfold" => {
+            let k = parse(k)? as usize;
+            let ans: u128 = rows.iter().map(|r| solve(&unfold(r, k))).sum();
+            println!("{}", ans);
+        }
+        [flag, line] if flag == "--list" => {
+            for arrangement in arrangements(get_row(line)?) {
+                println!("{}", arrangement);
+            }
+        }
+        [flag, line, k] if flag == "--kth" => {
+            let k: u128 = k.parse().map_err(|_| bad_arg(k))?;
+            match kth_arrangement(get_row(line)?, k) {
+                Some(arrangement) => println!("{}", arrangement),
+                None => println!("only {} arrangements", solve(get_row(line)?)),
+            }
+        }
+        [flag, line, n, seed @ ..] if flag == "--sample" && seed.len() <= 1 => {
+            let seed = seed.first().map_or(Ok(1), parse)?;
+            for arrangement in sample(get_row(line)?, parse(n)? as usize, seed) {
+                println!("{}", arrangement);
+            }
+        }
+        [flag, line, copies @ ..] if flag == "--deduce" && copies.len() <= 1 => {
+            let k = copies.first().map_or(Ok(1), parse)?;
+            report_unknowns(&unfold(get_row(line)?, k as usize));
+        }
+        _ => {
+            let usage = "usage: day12 [--unfold <k> | --list <line> | --kth <line> <k> | \
+                         --sample <line> <n> [seed] | --deduce <line> [copies] | --nonogram <file>]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
@@ -96,6 +353,74 @@ fn main() -> io::Result<()> {
 #[test]
 fn test() {
     let s = "?###???????? 3,2,1";
-    let row = quintuple(&parse_row(s).unwrap());
+    let row = unfold(&parse_row(s).unwrap(), 5);
     assert_eq!(solve(&row), 506250);
+
+    let row = parse_row("?###???????? 3,2,1").unwrap();
+    assert_eq!(solve(&row), 10);
+    assert_eq!(unfold(&row, 1), row);
+    assert_eq!(solve(&unfold(&row, 0)), 1);
+    // 10 * 15^(k - 1), far past usize
+    let huge = 10 * 15_u128.pow(29);
+    assert_eq!(solve(&unfold(&row, 30)), huge);
+    assert_eq!(suffix_counts(&unfold(&row, 30))[0][0], huge);
+    let all: Vec<String> = arrangements(&row).collect();
+    assert_eq!(all.len(), 10);
+    assert!(all.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(all[0], ".###.##.#...");
+    assert_eq!(kth_arrangement(&row, 9).as_deref(), Some(".###....##.#"));
+    assert_eq!(kth_arrangement(&row, 10), None);
+    assert!(sample(&row, 20, 7).iter().all(|a| all.contains(a)));
+
+    let input = fs::read_to_string("test.txt").unwrap();
+    let rows: Vec<Row> = input.lines().filter_map(parse_row).collect();
+    assert_eq!(rows.iter().map(solve).sum::<u128>(), 21);
+    assert_eq!(
+        rows.iter().map(|r| solve(&unfold(r, 5))).sum::<u128>(),
+        525152
+    );
+    for row in rows {
+        assert_eq!(suffix_counts(&row)[0][0], solve(&row));
+        assert_eq!(arrangements(&row).count() as u128, solve(&row));
+        let damaged: Vec<u128> = (0..row.0.len())
+            .map(|i| {
+                arrangements(&row)
+                    .filter(|a| a.as_bytes()[i] == b'#')
+                    .count() as u128
+            })
+            .collect();
+        assert_eq!(damaged_counts(&row), damaged);
+    }
+
+    let row = parse_row("?###???????? 3,2,1").unwrap();
+    let cells = deduce(&row).unwrap();
+    assert_eq!(
+        cells[..5],
+        [
+            Cell::Operational,
+            Cell::Damaged,
+            Cell::Damaged,
+            Cell::Damaged,
+            Cell::Operational
+        ]
+    );
+    assert!(cells[5..].iter().all(|&c| c == Cell::Unknown));
+    let big = unfold(&row, 5);
+    let counts = damaged_counts(&big);
+    assert_eq!(counts[1], 506250);
+    assert!(counts.iter().all(|&n| n <= 506250));
+    assert_eq!(deduce(&parse_row("#.# 3").unwrap()), None);
+
+    let puzzle =
+        nonogram::parse_puzzle("rows\n1 1\n3\n0\n1\ncols\n2\n1\n2\n0\n; column 5\n1").unwrap();
+    let solutions = puzzle.solve();
+    assert!(solutions.unique);
+    let picture = nonogram::render(&solutions.grid.unwrap());
+    assert_eq!(picture.lines().next(), Some("██  ██    "));
+    // a diagonal pair goes either way
+    let puzzle = nonogram::parse_puzzle("rows\n1\n1\ncols\n1\n1").unwrap();
+    assert!(!puzzle.solve().unique);
+    let puzzle = nonogram::parse_puzzle("rows\n2\n0\ncols\n0\n0").unwrap();
+    assert_eq!(puzzle.solve().grid, None);
+    assert!(nonogram::parse_puzzle("1 1\nrows\n1").is_err());
 }
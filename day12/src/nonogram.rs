@@ -0,0 +1,159 @@
+use super::{deduce, Cell};
+
+// A picture to fill in, with the clue of each row and each column. A clue is
+// the lengths of the runs of filled cells in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+pub type Grid = Vec<Vec<Cell>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solutions {
+    // the first solution found
+    pub grid: Option<Grid>,
+    // whether there's no other
+    pub unique: bool,
+}
+
+// The clues are given as
+//   rows
+//   1 1
+//   3
+//   cols
+//   2
+//   ...
+// with one clue per line, and 0 for a line that's left empty. Lines starting
+// with ';' are comments.
+pub fn parse_puzzle(s: &str) -> Result<Puzzle, String> {
+    let mut rows: Vec<Vec<usize>> = Vec::new();
+    let mut cols: Vec<Vec<usize>> = Vec::new();
+    let mut section: Option<&mut Vec<Vec<usize>>> = None;
+    for (i, l) in s.lines().enumerate() {
+        let l = l.trim();
+        match l {
+            "" => continue,
+            _ if l.starts_with(';') => continue,
+            "rows" => section = Some(&mut rows),
+            "cols" => section = Some(&mut cols),
+            _ => {
+                let clue: Vec<usize> = l
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse().ok())
+                    .collect::<Option<_>>()
+                    .ok_or(format!("line {}: bad clue {:?}", i + 1, l))?;
+                let clue = match clue[..] {
+                    [0] => Vec::new(),
+                    _ if clue.contains(&0) => return Err(format!("line {}: empty run", i + 1)),
+                    _ => clue,
+                };
+                section
+                    .as_mut()
+                    .ok_or(format!("line {}: clue before rows or cols", i + 1))?
+                    .push(clue);
+            }
+        }
+    }
+    if rows.is_empty() || cols.is_empty() {
+        return Err("no rows or no cols".to_string());
+    }
+    Ok(Puzzle { rows, cols })
+}
+
+// Fill in what the clue forces on a line, returning whether anything changed,
+// or None if the line can't be filled.
+fn solve_line(line: &mut [Cell], clue: &[usize]) -> Option<bool> {
+    let springs: Vec<char> = line
+        .iter()
+        .map(|c| match c {
+            Cell::Damaged => '#',
+            Cell::Operational => '.',
+            Cell::Unknown => '?',
+        })
+        .collect();
+    let forced = deduce(&(springs, clue.to_vec()))?;
+    let changed = line != &forced[..];
+    line.copy_from_slice(&forced);
+    Some(changed)
+}
+
+impl Puzzle {
+    // Solve the rows and columns one line at a time until nothing changes.
+    // Returns false on a contradiction.
+    fn propagate(&self, grid: &mut Grid) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (row, clue) in grid.iter_mut().zip(self.rows.iter()) {
+                match solve_line(row, clue) {
+                    Some(c) => changed |= c,
+                    None => return false,
+                }
+            }
+            for (j, clue) in self.cols.iter().enumerate() {
+                let mut col: Vec<Cell> = grid.iter().map(|r| r[j]).collect();
+                match solve_line(&mut col, clue) {
+                    Some(false) => {}
+                    Some(true) => {
+                        changed = true;
+                        for (r, c) in grid.iter_mut().zip(col) {
+                            r[j] = c;
+                        }
+                    }
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+
+    // Deduce what can be, then guess the first unknown cell both ways,
+    // stopping once `limit` solutions are found.
+    fn search(&self, mut grid: Grid, found: &mut Vec<Grid>, limit: usize) {
+        if found.len() >= limit || !self.propagate(&mut grid) {
+            return;
+        }
+        let unknown = grid
+            .iter()
+            .enumerate()
+            .find_map(|(i, r)| r.iter().position(|&c| c == Cell::Unknown).map(|j| (i, j)));
+        match unknown {
+            None => found.push(grid),
+            Some((i, j)) => {
+                for guess in [Cell::Damaged, Cell::Operational] {
+                    let mut next = grid.clone();
+                    next[i][j] = guess;
+                    self.search(next, found, limit);
+                }
+            }
+        }
+    }
+
+    pub fn solve(&self) -> Solutions {
+        let grid = vec![vec![Cell::Unknown; self.cols.len()]; self.rows.len()];
+        let mut found: Vec<Grid> = Vec::new();
+        self.search(grid, &mut found, 2);
+        Solutions {
+            unique: found.len() == 1,
+            grid: found.into_iter().next(),
+        }
+    }
+}
+
+pub fn render(grid: &Grid) -> String {
+    grid.iter()
+        .map(|r| {
+            r.iter()
+                .map(|c| match c {
+                    Cell::Damaged => "██",
+                    Cell::Operational => "  ",
+                    Cell::Unknown => "??",
+                })
+                .collect::<String>()
+                + "\n"
+        })
+        .collect()
+}
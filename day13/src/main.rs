@@ -1,8 +1,7 @@
 use std::cmp::min;
+use std::env;
 use std::fs;
 use std::io;
-use std::iter;
-use std::str;
 
 type Grid = Vec<Vec<char>>;
 
@@ -20,114 +19,128 @@ fn parse_grid(s: &str) -> Grid {
 }
 
 fn transpose(g: &Grid) -> Grid {
-    let n = g.len();
-    let m = g[0].len();
-    let mut gt: Grid = iter::repeat_with(|| vec!['\0'; n]).take(m).collect();
-    for i in 0..n {
-        for j in 0..m {
-            gt[j][i] = g[i][j];
-        }
-    }
-    gt
+    let m = g.first().map_or(0, |r| r.len());
+    (0..m).map(|j| g.iter().map(|r| r[j]).collect()).collect()
 }
 
-fn horizontal_refl(g: &Grid) -> Option<Vec<usize>> {
-    let mut refls: Vec<usize> = Vec::new();
-    let n = g.len();
-    for i in 0..n - 1 {
-        let mut refl = true;
-        for k in 0..min(i + 1, n - i - 1) {
-            if g[i + k + 1] != g[i - k] {
-                refl = false;
-                break;
-            }
-        }
-        if refl {
-            refls.push(i);
-        }
-    }
-    if refls.len() > 0 {
-        Some(refls)
-    } else {
-        None
+// Each row as bits, with '#' set, in as many words as it takes.
+fn pack(g: &Grid) -> Result<Vec<Vec<u64>>, String> {
+    let m = g.first().map_or(0, |r| r.len());
+    if m == 0 {
+        return Err("empty grid".to_string());
     }
+    g.iter()
+        .map(|r| {
+            if r.len() != m {
+                return Err("rows of different lengths".to_string());
+            }
+            r.chunks(u64::BITS as usize)
+                .map(|chunk| {
+                    chunk.iter().try_fold(0, |bits, &c| match c {
+                        '#' => Ok(bits << 1 | 1),
+                        '.' => Ok(bits << 1),
+                        _ => Err(format!("bad cell {:?}", c)),
+                    })
+                })
+                .collect()
+        })
+        .collect()
 }
 
-fn solve(g: &Grid) -> Mirror {
-    if let Some(rs) = horizontal_refl(g) {
-        return Horiz(rs[0]);
-    } else {
-        let g_t = transpose(g);
-        let rs = horizontal_refl(&g_t).unwrap();
-        return Vert(rs[0]);
-    }
+// the number of cells that differ between two packed lines
+fn mismatches(a: &[u64], b: &[u64]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
 }
 
-fn solve2(g: &mut Grid) -> Mirror {
-    let n = g.len();
-    let m = g[0].len();
-
-    let old_mirror = solve(g);
-    for i in 0..n {
-        for j in 0..m {
-            let c = g[i][j];
-            g[i][j] = if c == '#' { '.' } else { '#' };
-            match horizontal_refl(g) {
-                Some(rs) => {
-                    for &r in rs.iter() {
-                        if Horiz(r) != old_mirror {
-                            return Horiz(r);
-                        }
-                    }
+// The axes with exactly k mismatched cells across them, where axis i lies
+// between lines i and i + 1. Counting stops once an axis passes k.
+fn refl_axes(lines: &[Vec<u64>], k: u32) -> Vec<usize> {
+    (0..lines.len().saturating_sub(1))
+        .filter(|&i| {
+            let mut smudges = 0;
+            for d in 0..min(i + 1, lines.len() - i - 1) {
+                smudges += mismatches(&lines[i - d], &lines[i + 1 + d]);
+                if smudges > k {
+                    return false;
                 }
-                None => {}
             }
-            g[i][j] = c;
-        }
-    }
+            smudges == k
+        })
+        .collect()
+}
 
-    let mut g_t = transpose(g);
-    for j in 0..m {
-        for i in 0..n {
-            let c = g_t[j][i];
-            g_t[j][i] = if c == '#' { '.' } else { '#' };
-            match horizontal_refl(&g_t) {
-                Some(rs) => {
-                    for &r in rs.iter() {
-                        if Vert(r) != old_mirror {
-                            return Vert(r);
-                        }
-                    }
-                }
-                None => {}
-            }
-            g_t[j][i] = c;
-        }
+// every mirror with exactly k smudges, horizontal ones first
+fn reflections(g: &Grid, k: u32) -> Result<Vec<Mirror>, String> {
+    let rows = pack(g)?;
+    let cols = pack(&transpose(g))?;
+    let horiz = refl_axes(&rows, k).into_iter().map(Horiz);
+    let vert = refl_axes(&cols, k).into_iter().map(Vert);
+    Ok(horiz.chain(vert).collect())
+}
+
+fn find_mirror(g: &Grid, k: u32) -> Result<Mirror, String> {
+    reflections(g, k)?
+        .into_iter()
+        .next()
+        .ok_or(format!("no mirror with {} smudges", k))
+}
+
+fn solve(g: &Grid) -> Result<Mirror, String> {
+    find_mirror(g, 0)
+}
+
+fn solve2(g: &Grid) -> Result<Mirror, String> {
+    find_mirror(g, 1)
+}
+
+fn score(m: &Mirror) -> usize {
+    match m {
+        Horiz(i) => 100 * (i + 1),
+        Vert(i) => i + 1,
     }
-    panic!("no mirror found");
 }
 
 fn main() -> io::Result<()> {
     let input = fs::read_to_string("input.txt")?;
-    let mut grids: Vec<Grid> = input.split("\n\n").map(|s| parse_grid(s)).collect();
-
-    let ans1: usize = grids
-        .iter_mut()
-        .map(|g| match solve(g) {
-            Horiz(i) => 100 * (i + 1),
-            Vert(i) => i + 1,
-        })
-        .sum();
-    println!("{}", ans1);
-
-    let ans2: usize = grids
-        .iter_mut()
-        .map(|g| match solve2(g) {
-            Horiz(i) => 100 * (i + 1),
-            Vert(i) => i + 1,
+    let grids: Vec<Grid> = input
+        .split("\n\n")
+        .map(parse_grid)
+        .filter(|g| !g.is_empty())
+        .collect();
+
+    // the sum over the grids, naming the first that has no mirror
+    let total = |find: &dyn Fn(&Grid) -> Result<Mirror, String>| -> io::Result<usize> {
+        grids.iter().enumerate().try_fold(0, |sum, (i, g)| {
+            let m = find(g).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("grid {}: {}", i + 1, e))
+            })?;
+            Ok(sum + score(&m))
         })
-        .sum();
-    println!("{}", ans2);
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {
+            println!("{}", total(&solve)?);
+            println!("{}", total(&solve2)?);
+        }
+        // every mirror of each grid with k smudges, then the total
+        [flag, k] if flag == "--smudges" => {
+            let k: u32 = k.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("bad count {}", k))
+            })?;
+            for (i, g) in grids.iter().enumerate() {
+                let mirrors =
+                    reflections(g, k).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                println!("{:>4} {:?}", i + 1, mirrors);
+            }
+            println!("{}", total(&|g| find_mirror(g, k))?);
+        }
+        _ => {
+            let usage = "usage: day13 [--smudges <k>]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
@@ -151,11 +164,33 @@ fn test() {
 ..##..###
 #....#..#";
 
-    let mut g1 = parse_grid(&first);
-    let mut g2 = parse_grid(&second);
-
-    assert_eq!(solve(&g1), Vert(4));
-    assert_eq!(solve(&g2), Horiz(3));
-    assert_eq!(solve2(&mut g1), Horiz(2));
-    assert_eq!(solve2(&mut g2), Horiz(0));
+    let g1 = parse_grid(first);
+    let g2 = parse_grid(second);
+
+    assert_eq!(solve(&g1), Ok(Vert(4)));
+    assert_eq!(solve(&g2), Ok(Horiz(3)));
+    assert_eq!(solve2(&g1), Ok(Horiz(2)));
+    assert_eq!(solve2(&g2), Ok(Horiz(0)));
+
+    assert_eq!(reflections(&g1, 0), Ok(vec![Vert(4)]));
+    assert_eq!(reflections(&g2, 1), Ok(vec![Horiz(0)]));
+    assert_eq!(reflections(&g1, 5), Ok(vec![Horiz(0), Horiz(5)]));
+    assert_eq!(reflections(&g1, 2), Ok(vec![Vert(0)]));
+    assert!(solve(&parse_grid("#.\n..")).is_err());
+    assert!(solve(&parse_grid("#.\n.")).is_err());
+    assert!(solve(&parse_grid("#x\n#x")).is_err());
+
+    // wider and taller than a word, mirrored after column 69
+    let mut big: Grid = vec![vec!['.'; 140]; 160];
+    for (i, row) in big.iter_mut().enumerate() {
+        for j in 0..70 {
+            let c = if (i * 7 + j * 3) % 5 < 2 { '#' } else { '.' };
+            row[j] = c;
+            row[139 - j] = c;
+        }
+    }
+    assert_eq!(reflections(&big, 0), Ok(vec![Vert(69)]));
+    big[3][2] = '#';
+    assert_eq!(big[3][137], '.');
+    assert_eq!(solve2(&big), Ok(Vert(69)));
 }
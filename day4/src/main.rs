@@ -1,3 +1,4 @@
+use std::env;
 use std::fs::File;
 use std::io::{self, prelude::*, BufReader};
 use std::str;
@@ -8,6 +9,44 @@ struct Card {
     hand: Vec<u32>,
 }
 
+// points awarded for a card with a given number of matches
+#[derive(Debug, Clone)]
+enum Scoring {
+    // 1, 2, 4, 8, ...
+    Geometric,
+    // 1, 2, 3, 4, ...
+    Linear,
+    // table[matches], saturating at the last entry
+    Table(Vec<u32>),
+}
+
+// which cards a winning card copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CopyWindow {
+    // the next `matches` cards, stopping at the end of the table
+    Next,
+    // the next `matches` cards, wrapping around to the first card
+    Wrap,
+}
+
+#[derive(Debug, Clone)]
+struct Rules {
+    scoring: Scoring,
+    window: CopyWindow,
+    // whether copies win copies themselves, or only the original cards do
+    cascade: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            scoring: Scoring::Geometric,
+            window: CopyWindow::Next,
+            cascade: true,
+        }
+    }
+}
+
 fn parse_card(l: &str) -> Option<Card> {
     let seq = l.split(": ").last()?;
     let (win_str, hand_str) = seq.split_once("| ")?;
@@ -23,43 +62,95 @@ fn parse_card(l: &str) -> Option<Card> {
     Some(Card { winning, hand })
 }
 
+impl Rules {
+    // With a wrapping window, copies can win copies of each other forever,
+    // so only the original cards may win.
+    fn validate(&self) -> Result<(), String> {
+        if self.window == CopyWindow::Wrap && self.cascade {
+            return Err("--window=wrap needs --no-cascade".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn parse_rules(args: &[String]) -> Result<Rules, String> {
+    let mut rules = Rules::default();
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("--scoring", "geometric")) => rules.scoring = Scoring::Geometric,
+            Some(("--scoring", "linear")) => rules.scoring = Scoring::Linear,
+            Some(("--scoring", table)) => {
+                let table: Vec<u32> = table
+                    .split(',')
+                    .map(|s| s.parse().ok())
+                    .collect::<Option<_>>()
+                    .ok_or(format!("bad scoring table {}", table))?;
+                rules.scoring = Scoring::Table(table);
+            }
+            Some(("--window", "next")) => rules.window = CopyWindow::Next,
+            Some(("--window", "wrap")) => rules.window = CopyWindow::Wrap,
+            None if arg == "--no-cascade" => rules.cascade = false,
+            _ => return Err(format!("bad argument {}", arg)),
+        }
+    }
+    rules.validate()?;
+    Ok(rules)
+}
+
 fn count_matches(c: &Card) -> u32 {
     let count = c.hand.iter().filter(|x| c.winning.contains(x)).count();
     count as u32
 }
 
-fn solve1(cs: &Vec<Card>) -> u32 {
-    return cs
-        .iter()
-        .map(|c| {
-            let matches = count_matches(&c);
-            if matches == 0 {
-                0
-            } else {
-                let exp = (matches as u32) - 1;
-                (2_u32).pow(exp)
-            }
-        })
-        .sum();
+fn points(scoring: &Scoring, matches: u32) -> u32 {
+    match scoring {
+        Scoring::Geometric if matches == 0 => 0,
+        Scoring::Geometric => 2_u32.pow(matches - 1),
+        Scoring::Linear => matches,
+        Scoring::Table(table) => table
+            .get(matches as usize)
+            .or(table.last())
+            .copied()
+            .unwrap_or(0),
+    }
+}
+
+fn total_points(cs: &[Card], rules: &Rules) -> u32 {
+    cs.iter()
+        .map(|c| points(&rules.scoring, count_matches(c)))
+        .sum()
 }
 
-fn solve2(cs: &Vec<Card>) -> u32 {
+// Cards are processed once in order, which is only enough for a wrapping
+// window because rules that pass validate don't cascade with one.
+fn total_cards(cs: &[Card], rules: &Rules) -> u32 {
     let len = cs.len();
-    let scores: Vec<u32> = cs.iter().map(|c| count_matches(c)).collect();
+    let scores: Vec<u32> = cs.iter().map(count_matches).collect();
     let mut num_cards = vec![1; len];
 
     for (i, score) in scores.iter().enumerate() {
         let s = *score as usize;
+        let won = if rules.cascade { num_cards[i] } else { 1 };
         for j in (i + 1)..=(i + s) {
-            if j >= len {
-                break;
-            }
-            num_cards[j] += num_cards[i];
+            let j = match rules.window {
+                CopyWindow::Next if j >= len => break,
+                CopyWindow::Next => j,
+                CopyWindow::Wrap => j % len,
+            };
+            num_cards[j] += won;
         }
     }
     num_cards.into_iter().sum()
 }
 
+fn solve1(cs: &[Card]) -> u32 {
+    total_points(cs, &Rules::default())
+}
+
+fn solve2(cs: &[Card]) -> u32 {
+    total_cards(cs, &Rules::default())
+}
+
 fn main() -> io::Result<()> {
     let file = File::open("input.txt")?;
     let reader = BufReader::new(file);
@@ -69,8 +160,51 @@ fn main() -> io::Result<()> {
         .filter_map(|l| parse_card(&l.unwrap()))
         .collect();
 
-    println!("{}", solve1(&cards));
-    println!("{}", solve2(&cards));
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        println!("{}", solve1(&cards));
+        println!("{}", solve2(&cards));
+    } else {
+        let usage =
+            "usage: day4 [--scoring=geometric|linear|N,N,...] [--window=next|wrap] [--no-cascade]";
+        let rules = parse_rules(&args).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{}\n{}", e, usage))
+        })?;
+        println!("{}", total_points(&cards, &rules));
+        println!("{}", total_cards(&cards, &rules));
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let file = File::open("test.txt").unwrap();
+    let reader = BufReader::new(file);
+    let cards: Vec<Card> = reader
+        .lines()
+        .filter_map(|l| parse_card(&l.unwrap()))
+        .collect();
+    assert_eq!(solve1(&cards), 13);
+    assert_eq!(solve2(&cards), 30);
+
+    let args = |a: &[&str]| -> Vec<String> { a.iter().map(|s| s.to_string()).collect() };
+    let rules = parse_rules(&args(&["--scoring=linear"])).unwrap();
+    assert_eq!(total_points(&cards, &rules), 9);
+    let rules = parse_rules(&args(&["--scoring=0,1,5"])).unwrap();
+    assert_eq!(total_points(&cards, &rules), 16);
+    let rules = parse_rules(&args(&["--no-cascade"])).unwrap();
+    assert_eq!(total_cards(&cards, &rules), 15);
+    assert!(parse_rules(&args(&["--window=wrap"])).is_err());
+    assert!(parse_rules(&args(&["--scoring=1,x"])).is_err());
+
+    // the last card wins copies of the first two
+    let wrapping: Vec<Card> = ["1 | 2", "1 | 2", "1 2 | 1 2"]
+        .iter()
+        .filter_map(|l| parse_card(l))
+        .collect();
+    let rules = parse_rules(&args(&["--window=wrap", "--no-cascade"])).unwrap();
+    assert_eq!(total_cards(&wrapping, &rules), 5);
+    let rules = parse_rules(&args(&["--no-cascade"])).unwrap();
+    assert_eq!(total_cards(&wrapping, &rules), 3);
+}
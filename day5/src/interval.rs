@@ -0,0 +1,112 @@
+use std::iter::FromIterator;
+
+// half-open [lo, hi)
+pub type Interval = (u64, u64);
+
+// A set of keys, kept as sorted and disjoint intervals with gaps between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    invls: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn from_intervals(invls: Vec<Interval>) -> Self {
+        let mut set = IntervalSet { invls };
+        set.normalise();
+        set
+    }
+
+    // Sort the intervals, dropping empty ones and merging the ones that
+    // overlap or touch.
+    fn normalise(&mut self) {
+        self.invls.retain(|(lo, hi)| lo < hi);
+        self.invls.sort();
+        let mut merged: Vec<Interval> = Vec::with_capacity(self.invls.len());
+        for &(lo, hi) in self.invls.iter() {
+            match merged.last_mut() {
+                Some(last) if last.1 >= lo => last.1 = last.1.max(hi),
+                _ => merged.push((lo, hi)),
+            }
+        }
+        self.invls = merged;
+    }
+
+    pub fn intervals(&self) -> &[Interval] {
+        &self.invls
+    }
+
+    // number of keys in the set
+    pub fn len(&self) -> u64 {
+        self.invls.iter().map(|(lo, hi)| hi - lo).sum()
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.invls.first().map(|&(lo, _)| lo)
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, key: u64) -> bool {
+        let i = self.invls.partition_point(|&(_, hi)| hi <= key);
+        matches!(self.invls.get(i), Some(&(lo, _)) if lo <= key)
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::from_intervals([&self.invls[..], &other.invls[..]].concat())
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let (a, b) = (&self.invls, &other.invls);
+        let mut invls: Vec<Interval> = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            let lo = a[i].0.max(b[j].0);
+            let hi = a[i].1.min(b[j].1);
+            if lo < hi {
+                invls.push((lo, hi));
+            }
+            if a[i].1 < b[j].1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { invls }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut invls: Vec<Interval> = Vec::new();
+        let mut j = 0;
+        for &(lo, hi) in self.invls.iter() {
+            let mut lo = lo;
+            // skip the removed intervals that end before this one
+            while j < other.invls.len() && other.invls[j].1 <= lo {
+                j += 1;
+            }
+            let mut k = j;
+            while lo < hi && k < other.invls.len() && other.invls[k].0 < hi {
+                let (rlo, rhi) = other.invls[k];
+                if lo < rlo {
+                    invls.push((lo, rlo));
+                }
+                lo = lo.max(rhi);
+                k += 1;
+            }
+            if lo < hi {
+                invls.push((lo, hi));
+            }
+        }
+        IntervalSet { invls }
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(invl: Interval) -> Self {
+        IntervalSet::from_intervals(vec![invl])
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = Interval>>(iter: I) -> Self {
+        IntervalSet::from_intervals(iter.into_iter().collect())
+    }
+}
@@ -1,15 +1,71 @@
+mod interval;
+
+use interval::{Interval, IntervalSet};
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
 use std::io;
 use std::str;
 
-type Interval = (u64, u64);
-type RangeMap = Vec<[u64; 3]>;
+// the category the seed numbers belong to
+const SEED: &str = "seed";
+
+#[derive(Debug)]
+struct RangeMap {
+    src: String,
+    dest: String,
+    ranges: Vec<[u64; 3]>,
+}
 
 #[derive(Debug)]
 struct Almanac {
     seeds: Vec<u64>,
-    ranges: Vec<RangeMap>,
+    // range maps keyed by their source category
+    maps: HashMap<String, RangeMap>,
+}
+
+impl Almanac {
+    // Find the maps converting category `src` to `dest`, in order.
+    fn chain(&self, src: &str, dest: &str) -> Result<Vec<&RangeMap>, String> {
+        let mut chain = Vec::new();
+        let mut cat = src;
+        while cat != dest {
+            let rm = self
+                .maps
+                .get(cat)
+                .ok_or(format!("no map from {} towards {}", cat, dest))?;
+            chain.push(rm);
+            cat = &rm.dest;
+        }
+        Ok(chain)
+    }
+
+    // Compose the maps converting category `src` to `dest` into a single map.
+    fn collapse(&self, src: &str, dest: &str) -> Result<RangeMap, String> {
+        let id = RangeMap {
+            src: src.to_string(),
+            dest: src.to_string(),
+            ranges: Vec::new(),
+        };
+        Ok(self
+            .chain(src, dest)?
+            .into_iter()
+            .fold(id, |acc, rm| compose(&acc, rm)))
+    }
+
+    // The keys of category `src` that convert into `keys` of category `dest`.
+    fn preimage(&self, src: &str, dest: &str, keys: &IntervalSet) -> Result<IntervalSet, String> {
+        Ok(preimage(&self.collapse(src, dest)?, keys))
+    }
+
+    fn seed_intervals(&self) -> IntervalSet {
+        self.seeds
+            .iter()
+            .tuples::<(_, _)>()
+            .map(|(&start, &len)| (start, start + len))
+            .collect()
+    }
 }
 
 fn parse_range(l: &str) -> Option<[u64; 3]> {
@@ -21,119 +77,342 @@ fn parse_range(l: &str) -> Option<[u64; 3]> {
     }
 }
 
-fn parse_almanac(inp: &str) -> Option<Almanac> {
-    let (seed_str, rest) = inp.split_once("\n\n")?;
+// "seed-to-soil map:" -> ("seed", "soil")
+fn parse_header(l: &str) -> Option<(String, String)> {
+    let (src, dest) = l.strip_suffix(" map:")?.split_once("-to-")?;
+    if src.is_empty() || dest.is_empty() {
+        None
+    } else {
+        Some((src.to_string(), dest.to_string()))
+    }
+}
+
+fn parse_range_map(s: &str) -> Result<RangeMap, String> {
+    let mut lines = s.lines();
+    let header = lines.next().unwrap_or_default();
+    let (src, dest) = parse_header(header).ok_or(format!("bad map header: {:?}", header))?;
+    let mut ranges: Vec<[u64; 3]> = lines
+        .map(|l| parse_range(l).ok_or(format!("bad range in {} map: {:?}", src, l)))
+        .collect::<Result<_, _>>()?;
+    ranges.sort_by_key(|r| r[1]);
+    if ranges.windows(2).any(|w| w[0][1] + w[0][2] > w[1][1]) {
+        return Err(format!("overlapping ranges in {} map", src));
+    }
+    normalise(&mut ranges);
+    Ok(RangeMap { src, dest, ranges })
+}
+
+fn parse_almanac(inp: &str) -> Result<Almanac, String> {
+    let (seed_str, rest) = inp.split_once("\n\n").ok_or("missing range maps")?;
     let seeds: Vec<u64> = seed_str
-        .split(": ")
-        .last()?
-        .split(" ")
+        .strip_prefix("seeds: ")
+        .ok_or("missing seeds")?
+        .split(' ')
         .filter_map(|s| s.parse().ok())
         .collect();
 
-    let mut ranges: Vec<RangeMap> = Vec::new();
-    for r_str in rest.split("\n\n") {
-        let mut range: RangeMap = r_str
-            .lines()
-            .skip(1)
-            .filter_map(|l| parse_range(l))
-            .collect();
-        range.sort_by(|r1, r2| r1[1].cmp(&r2[1]));
+    let mut maps: HashMap<String, RangeMap> = HashMap::new();
+    for r_str in rest.trim_end().split("\n\n") {
+        let rm = parse_range_map(r_str)?;
+        if maps.contains_key(&rm.src) {
+            return Err(format!("more than one map from {}", rm.src));
+        }
+        maps.insert(rm.src.clone(), rm);
+    }
 
-        ranges.push(range);
+    // every category leads to at most one other, so a revisit is a cycle
+    for start in maps.keys() {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut cat = start.as_str();
+        while let Some(rm) = maps.get(cat) {
+            if !seen.insert(cat) {
+                return Err(format!("maps from {} form a cycle", start));
+            }
+            cat = &rm.dest;
+        }
+    }
+    if !maps.contains_key(SEED) {
+        return Err(format!("no map from {}", SEED));
     }
-    Some(Almanac { seeds, ranges })
+    Ok(Almanac { seeds, maps })
 }
 
-fn query_map(rm: &RangeMap, key: u64) -> u64 {
-    for range in rm.iter() {
-        let &[dest, src, len] = range;
-        if key >= src && key < src + len {
-            return dest + (key - src);
+// Sort the ranges by source, dropping identity ranges and merging
+// contiguous ones, so that lookups can binary search.
+fn normalise(ranges: &mut Vec<[u64; 3]>) {
+    ranges.retain(|&[dest, src, len]| len > 0 && dest != src);
+    ranges.sort_by_key(|r| r[1]);
+    let mut merged: Vec<[u64; 3]> = Vec::with_capacity(ranges.len());
+    for &[dest, src, len] in ranges.iter() {
+        if let Some(last) = merged.last_mut() {
+            if last[1] + last[2] == src && last[0] + last[2] == dest {
+                last[2] += len;
+                continue;
+            }
         }
+        merged.push([dest, src, len]);
     }
-    key
+    *ranges = merged;
+}
+
+// the keys that a range map moves
+fn sources(rm: &RangeMap) -> IntervalSet {
+    rm.ranges
+        .iter()
+        .map(|&[_, src, len]| (src, src + len))
+        .collect()
+}
+
+// Split the whole key space into ranges, filling the gaps with identity ranges.
+fn pieces(rm: &RangeMap) -> Vec<[u64; 3]> {
+    let gaps = IntervalSet::from((0, u64::MAX)).difference(&sources(rm));
+    let mut pieces = rm.ranges.clone();
+    pieces.extend(gaps.intervals().iter().map(|&(lo, hi)| [lo, lo, hi - lo]));
+    pieces
 }
 
-// Map an interval given the range map.
+// The map applying `a` and then `b`.
+fn compose(a: &RangeMap, b: &RangeMap) -> RangeMap {
+    let mut ranges = Vec::new();
+    for [dest, src, len] in pieces(a) {
+        let mut key = src;
+        for (lo, hi) in query_map_range(b, (dest, dest + len)) {
+            ranges.push([lo, key, hi - lo]);
+            key += hi - lo;
+        }
+    }
+    normalise(&mut ranges);
+    RangeMap {
+        src: a.src.clone(),
+        dest: b.dest.clone(),
+        ranges,
+    }
+}
+
+fn query_map(rm: &RangeMap, key: u64) -> u64 {
+    let i = rm.ranges.partition_point(|r| r[1] + r[2] <= key);
+    match rm.ranges.get(i) {
+        Some(&[dest, src, _]) if src <= key => dest + (key - src),
+        _ => key,
+    }
+}
+
+// Map an interval given the range map. The images come out in order of the
+// keys they came from.
 fn query_map_range(rm: &RangeMap, invl: Interval) -> Vec<Interval> {
     let mut invls: Vec<Interval> = Vec::new();
     let (mut lo, hi) = invl;
-    for range in rm.iter() {
-        let &[dest, src, len] = range;
-        // case equal
-        if lo == src && hi == src + len {
-            invls.push((dest, dest + len));
-            return invls;
-        }
-        // case [ (  ) ]
-        else if (lo >= src && lo < src + len) && (hi >= src && hi < src + len) {
-            invls.push((dest + (lo - src), dest + (hi - src)));
-            return invls;
-        }
-        // case ( [   ] )
-        else if lo <= src && hi >= src + len {
-            invls.push((lo, src - 1));
-            invls.push((dest, dest + len));
-            lo = src + len;
-        }
-        // case ( [---) ]
-        else if lo < src && hi >= src && hi < src + len {
-            invls.push((lo, src - 1));
-            invls.push((dest, dest + (hi - src)));
-            return invls;
-        }
-        // case [ (---] )
-        else if lo >= src && lo < src + len && hi > src + len {
-            invls.push((dest + (lo - src), dest + len));
-            lo = src + len;
+    let mut i = rm.ranges.partition_point(|r| r[1] + r[2] <= lo);
+    while lo < hi {
+        match rm.ranges.get(i) {
+            Some(&[dest, src, len]) if src <= lo => {
+                let end = hi.min(src + len);
+                invls.push((dest + (lo - src), dest + (end - src)));
+                lo = end;
+                i += 1;
+            }
+            Some(&[_, src, _]) => {
+                let end = hi.min(src);
+                invls.push((lo, end));
+                lo = end;
+            }
+            None => {
+                invls.push((lo, hi));
+                lo = hi;
+            }
         }
     }
-    if invls.is_empty() {
-        vec![invl]
-    } else {
-        invls
-    }
+    invls
 }
 
-fn seed_location(ranges: &Vec<RangeMap>, seed: u64) -> u64 {
-    ranges.iter().fold(seed, |k, rng| query_map(rng, k))
+// Move the keys lying in `from` so that `from` starts at `to`.
+fn shift(keys: &IntervalSet, from: Interval, to: u64) -> Vec<Interval> {
+    keys.intersection(&from.into())
+        .intervals()
+        .iter()
+        .map(|&(lo, hi)| (to + (lo - from.0), to + (hi - from.0)))
+        .collect()
 }
 
-fn interval_location(ranges: &Vec<RangeMap>, seed: Interval) -> Vec<Interval> {
-    let invls: Vec<Interval> = vec![seed];
-    return ranges.iter().fold(invls, |is, rng| {
-        is.iter().map(|i| query_map_range(rng, *i)).concat()
-    });
+// The keys that the range map sends the given keys to, with one binary
+// search into the map per interval of keys.
+fn image(rm: &RangeMap, keys: &IntervalSet) -> IntervalSet {
+    keys.intervals()
+        .iter()
+        .flat_map(|&invl| query_map_range(rm, invl))
+        .collect()
 }
 
-fn solve1(alm: &Almanac) -> u64 {
-    return alm
-        .seeds
+// The keys that the range map sends into the given keys.
+fn preimage(rm: &RangeMap, keys: &IntervalSet) -> IntervalSet {
+    let moved: IntervalSet = rm
+        .ranges
         .iter()
-        .map(|s| seed_location(&alm.ranges, *s))
-        .min()
-        .unwrap_or(0);
+        .flat_map(|&[dest, src, len]| shift(keys, (dest, dest + len), src))
+        .collect();
+    keys.difference(&sources(rm)).union(&moved)
+}
+
+// The lowest location reachable from the seeds, and the seeds that reach it.
+fn lowest_location(alm: &Almanac, seeds: &IntervalSet) -> Result<(u64, IntervalSet), String> {
+    let rm = alm.collapse(SEED, "location")?;
+    let loc = image(&rm, seeds).min().ok_or("no seeds")?;
+    let seeds = preimage(&rm, &(loc, loc + 1).into()).intersection(seeds);
+    Ok((loc, seeds))
+}
+
+// xorshift, so the checks need no dependencies
+#[cfg(test)]
+fn next_rand(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+// Compare the interval operations on the composed seed-to-location map with
+// mapping each key through the chain of maps one at a time.
+#[cfg(test)]
+fn check(alm: &Almanac, rounds: usize) -> Result<(), String> {
+    let chain = alm.chain(SEED, "location")?;
+    let rm = alm.collapse(SEED, "location")?;
+    let location = |key: u64| chain.iter().fold(key, |k, rm| query_map(rm, k));
+    // past every range, keys map to themselves
+    let bound = chain
+        .iter()
+        .flat_map(|rm| rm.ranges.iter())
+        .map(|&[dest, src, len]| dest.max(src) + len)
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    let mut state = 0x2545f4914f6cdd1d;
+    let mut rand_invl = |max_len: u64| {
+        let lo = next_rand(&mut state) % bound;
+        (lo, lo + 1 + next_rand(&mut state) % max_len)
+    };
+    for round in 0..rounds {
+        let seeds: IntervalSet = (0..3).map(|_| rand_invl(64)).collect();
+        let images: IntervalSet = seeds
+            .intervals()
+            .iter()
+            .flat_map(|&(lo, hi)| lo..hi)
+            .map(|k| (location(k), location(k) + 1))
+            .collect();
+        if image(&rm, &seeds) != images {
+            return Err(format!("round {}: image of {:?} differs", round, seeds));
+        }
+
+        let locs: IntervalSet = (0..3).map(|_| rand_invl(bound / 4 + 1)).collect();
+        let window = IntervalSet::from(rand_invl(256));
+        let keys: IntervalSet = window
+            .intervals()
+            .iter()
+            .flat_map(|&(lo, hi)| lo..hi)
+            .filter(|&k| locs.contains(location(k)))
+            .map(|k| (k, k + 1))
+            .collect();
+        if preimage(&rm, &locs).intersection(&window) != keys {
+            return Err(format!("round {}: preimage of {:?} differs", round, locs));
+        }
+    }
+    Ok(())
 }
 
-fn solve2(alm: &Almanac) -> u64 {
-    let invls: Vec<Interval> = alm
+fn solve1(alm: &Almanac) -> Result<u64, String> {
+    let rm = alm.collapse(SEED, "location")?;
+    Ok(alm
         .seeds
         .iter()
-        .tuples::<(_, _)>()
-        .map(|(&start, &len)| (start, start + len - 1))
-        .collect();
-    let locs: Vec<Interval> = invls
-        .into_iter()
-        .map(|invl| interval_location(&alm.ranges, invl))
-        .concat();
-    return locs.into_iter().map(|(lo, _)| lo).min().unwrap();
+        .map(|s| query_map(&rm, *s))
+        .min()
+        .unwrap_or(0))
+}
+
+fn solve2(alm: &Almanac) -> Result<u64, String> {
+    let (loc, _) = lowest_location(alm, &alm.seed_intervals())?;
+    Ok(loc)
 }
 
 fn main() -> io::Result<()> {
+    let to_io_err = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
     let inp_str = fs::read_to_string("input.txt")?;
-    let alm = parse_almanac(&inp_str).unwrap();
-    println!("{}", solve1(&alm));
-    println!("{}", solve2(&alm));
+    let alm = parse_almanac(&inp_str).map_err(to_io_err)?;
+
+    let parse_key = |key: &String| {
+        key.parse::<u64>()
+            .map_err(|_| to_io_err(format!("bad key: {}", key)))
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        // the lowest location, how many seeds reach it, and which ones
+        [flag] if flag == "--lowest" => {
+            let (loc, seeds) = lowest_location(&alm, &alm.seed_intervals()).map_err(to_io_err)?;
+            println!("{} {}", loc, seeds.len());
+            for (lo, hi) in seeds.intervals() {
+                println!("{} {}", lo, hi);
+            }
+        }
+        // the intervals of <from> that convert into [lo, hi) of <to>
+        [flag, src, dest, lo, hi] if flag == "--inverse" => {
+            let keys = IntervalSet::from((parse_key(lo)?, parse_key(hi)?));
+            for (lo, hi) in alm
+                .preimage(src, dest, &keys)
+                .map_err(to_io_err)?
+                .intervals()
+            {
+                println!("{} {}", lo, hi);
+            }
+        }
+        // converts keys between any two categories
+        [src, dest, keys @ ..] if !src.starts_with("--") => {
+            let rm = alm.collapse(src, dest).map_err(to_io_err)?;
+            for key in keys {
+                println!("{}", query_map(&rm, parse_key(key)?));
+            }
+        }
+        [] => {
+            println!("{}", solve1(&alm).map_err(to_io_err)?);
+            println!("{}", solve2(&alm).map_err(to_io_err)?);
+        }
+        _ => {
+            let usage = "usage: day5 [--lowest | --inverse <from> <to> <lo> <hi> | \
+                         <from> <to> [key ...]]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let input = fs::read_to_string("test.txt").unwrap();
+    let alm = parse_almanac(&input).unwrap();
+    assert_eq!(solve1(&alm), Ok(35));
+    assert_eq!(solve2(&alm), Ok(46));
+
+    let (loc, seeds) = lowest_location(&alm, &alm.seed_intervals()).unwrap();
+    assert_eq!(loc, 46);
+    assert_eq!(seeds.intervals(), &[(82, 83)]);
+
+    assert_eq!(check(&alm, 1000), Ok(()));
+
+    // seed 79 is soil 81 and humidity 78
+    let rm = alm.collapse("soil", "humidity").unwrap();
+    assert_eq!(query_map(&rm, 81), 78);
+    assert_eq!(alm.chain("soil", "humidity").unwrap().len(), 5);
+    assert!(alm.chain("humidity", "seed").is_err());
+
+    assert!(parse_almanac("seeds: 1\n\nseed-soil map:\n1 2 3").is_err());
+    assert!(parse_almanac("seeds: 1\n\nseed-to-soil map:\n1 2").is_err());
+    let twice = "seeds: 1\n\nseed-to-soil map:\n1 2 3\n\nseed-to-water map:\n4 5 6";
+    assert_eq!(
+        parse_almanac(twice).unwrap_err(),
+        "more than one map from seed"
+    );
+    let cycle = "seeds: 1\n\nseed-to-soil map:\n1 2 3\n\nsoil-to-seed map:\n4 5 6";
+    assert!(parse_almanac(cycle).unwrap_err().contains("cycle"));
+    let no_seed = "seeds: 1\n\nsoil-to-water map:\n1 2 3";
+    assert_eq!(parse_almanac(no_seed).unwrap_err(), "no map from seed");
+}
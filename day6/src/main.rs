@@ -1,69 +1,309 @@
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use std::env;
 use std::fs;
 use std::io;
 use std::iter::zip;
 use std::str;
 
-fn parse_races(inp: &str) -> Vec<(i64, i64)> {
+// (time, record distance)
+type Race = (u64, u64);
+
+fn parse_races(inp: &str) -> Vec<Race> {
     let mut lines = inp.lines();
     let t_str = lines.next().unwrap();
     let d_str = lines.next().unwrap();
-    let times: Vec<i64> = t_str
+    let times: Vec<u64> = t_str
         .split_ascii_whitespace()
         .filter_map(|d| d.parse().ok())
         .collect();
-    let dists: Vec<i64> = d_str
+    let dists: Vec<u64> = d_str
         .split_ascii_whitespace()
         .filter_map(|d| d.parse().ok())
         .collect();
     zip(times, dists).collect()
 }
 
-fn parse_big_race(inp: &str) -> (i64, i64) {
+// the concatenated numbers can have any number of digits
+fn parse_big_race(inp: &str) -> (BigUint, BigUint) {
     let mut lines = inp.lines();
     let t_str: String = lines
         .next()
         .unwrap()
         .chars()
-        .filter(|c| c.is_digit(10))
+        .filter(|c| c.is_ascii_digit())
         .collect();
     let d_str: String = lines
         .next()
         .unwrap()
         .chars()
-        .filter(|c| c.is_digit(10))
+        .filter(|c| c.is_ascii_digit())
         .collect();
-    let t: i64 = t_str.parse().unwrap();
-    let d: i64 = d_str.parse().unwrap();
+    let t: BigUint = t_str.parse().unwrap();
+    let d: BigUint = d_str.parse().unwrap();
     (t, d)
 }
 
-fn solve(race: &(i64, i64)) -> i64 {
+// The first hold time h with h * (t - h) > d; the last one is t - h. The
+// winning times lie strictly between the roots (t +- sqrt(t^2 - 4d)) / 2, so
+// start from the integer square root and step to the first winning h.
+fn first_win(race: &Race) -> Option<u64> {
+    let (t, d) = (race.0 as u128, race.1 as u128);
+    if t * t < 4 * d {
+        return None;
+    }
+    let s = (t * t - 4 * d).isqrt();
+    let wins = |h: u128| h * (t - h) > d;
+    let mut lo = (t - s) / 2;
+    while lo <= t / 2 && !wins(lo) {
+        lo += 1;
+    }
+    if lo > t / 2 {
+        return None;
+    }
+    while lo > 0 && wins(lo - 1) {
+        lo -= 1;
+    }
+    Some(lo as u64)
+}
+
+fn solve(race: &Race) -> u64 {
+    match first_win(race) {
+        Some(lo) => race.0 - 2 * lo + 1,
+        None => 0,
+    }
+}
+
+// How far a boat goes for a given hold time. The distance must rise (or stay
+// flat) up to its peak and then strictly fall, so the winning hold times can
+// be found by binary search when there's no closed form.
+trait BoatModel {
+    fn distance(&self, time: u64, hold: u64) -> u128;
+
+    // first and last winning hold times
+    fn winning_interval(&self, race: &Race) -> Option<(u64, u64)> {
+        search_interval(self, race)
+    }
+}
+
+// speed goes up by `rate` for every ms held
+struct Charge {
+    rate: u64,
+}
+
+// as `Charge`, but the speed can't go above `max_speed`
+struct Capped {
+    rate: u64,
+    max_speed: u64,
+}
+
+// as `Charge`, but the first `delay` ms held don't add any speed
+struct WarmUp {
+    rate: u64,
+    delay: u64,
+}
+
+impl BoatModel for Charge {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        (self.rate as u128 * hold as u128).saturating_mul((time - hold) as u128)
+    }
+
+    // rate * x > d exactly when x > d / rate
+    fn winning_interval(&self, race: &Race) -> Option<(u64, u64)> {
+        let &(t, d) = race;
+        let lo = first_win(&(t, d / self.rate.max(1)))?;
+        (self.rate > 0).then_some((lo, t - lo))
+    }
+}
+
+impl BoatModel for Capped {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        let speed = (self.rate as u128 * hold as u128).min(self.max_speed as u128);
+        speed.saturating_mul((time - hold) as u128)
+    }
+}
+
+impl BoatModel for WarmUp {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        let charged = hold.saturating_sub(self.delay) as u128;
+        (self.rate as u128 * charged).saturating_mul((time - hold) as u128)
+    }
+
+    // holding h is a plain race of length t - delay held for h - delay
+    fn winning_interval(&self, race: &Race) -> Option<(u64, u64)> {
+        let &(t, d) = race;
+        let charge = Charge { rate: self.rate };
+        let (lo, hi) = charge.winning_interval(&(t.checked_sub(self.delay)?, d))?;
+        Some((lo + self.delay, hi + self.delay))
+    }
+}
+
+fn search_interval<M: BoatModel + ?Sized>(model: &M, race: &Race) -> Option<(u64, u64)> {
     let &(t, d) = race;
-    let disc: f64 = (t * t - 4 * d) as f64;
-    if disc < 0.0 {
-        return 0;
+    let dist = |h: u64| model.distance(t, h);
+    // the peak is the first hold time after which the distance falls
+    let peak = partition_point(0, t, |h| dist(h + 1) >= dist(h));
+    if dist(peak) <= d as u128 {
+        return None;
+    }
+    let lo = partition_point(0, peak, |h| dist(h) <= d as u128);
+    let hi = partition_point(peak, t, |h| dist(h + 1) > d as u128);
+    Some((lo, hi))
+}
+
+// The first x in lo..=hi where `pred` stops holding, for `pred` true then false.
+fn partition_point(mut lo: u64, mut hi: u64, pred: impl Fn(u64) -> bool) -> u64 {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+fn count_wins(model: &dyn BoatModel, race: &Race) -> u64 {
+    match model.winning_interval(race) {
+        Some((lo, hi)) => hi - lo + 1,
+        None => 0,
+    }
+}
+
+// "charge:K", "capped:K:MAX" or "warmup:K:DELAY"
+fn parse_model(s: &str) -> Option<Box<dyn BoatModel>> {
+    let mut parts = s.split(':');
+    let name = parts.next()?;
+    let args: Vec<u64> = parts.map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match (name, args.as_slice()) {
+        ("charge", &[rate]) => Some(Box::new(Charge { rate })),
+        ("capped", &[rate, max_speed]) => Some(Box::new(Capped { rate, max_speed })),
+        ("warmup", &[rate, delay]) => Some(Box::new(WarmUp { rate, delay })),
+        _ => None,
+    }
+}
+
+// same as `solve`, for races too long for u64
+fn solve_big(t: &BigUint, d: &BigUint) -> BigUint {
+    let zero = BigUint::from(0u32);
+    let four_d = d * 4u32;
+    let t2 = t * t;
+    if t2 < four_d {
+        return zero;
     }
-    let hi = ((t as f64) + f64::sqrt(disc)) / 2.0;
-    let lo = ((t as f64) - f64::sqrt(disc)) / 2.0;
-    let mut range = (hi.floor() - lo.ceil()) as i64 + 1;
-    // exclude endpoints if zeros are ints
-    if hi.fract() == 0.0 {
-        range -= 1;
+    let half = t / 2u32;
+    let s = (t2 - four_d).sqrt();
+    let wins = |h: &BigUint| h * (t - h) > *d;
+    let mut lo = (t - &s) / 2u32;
+    while lo <= half && !wins(&lo) {
+        lo += 1u32;
     }
-    if lo.fract() == 0.0 {
-        range -= 1;
+    if lo > half {
+        return zero;
     }
-    range
+    while lo > zero && wins(&(&lo - 1u32)) {
+        lo -= 1u32;
+    }
+    t - &lo * 2u32 + 1u32
+}
+
+#[cfg(test)]
+fn brute_force(race: &Race) -> u64 {
+    let &(t, d) = race;
+    (0..=t).filter(|h| h * (t - h) > d).count() as u64
+}
+
+// Compare the solvers with counting every hold time on all small races, and
+// the closed forms of the boat models with searching.
+#[cfg(test)]
+fn check(max_time: u64) -> Result<(), String> {
+    let models: Vec<Box<dyn BoatModel>> = vec![
+        Box::new(Charge { rate: 3 }),
+        Box::new(Capped {
+            rate: 2,
+            max_speed: max_time / 3,
+        }),
+        Box::new(WarmUp { rate: 2, delay: 4 }),
+    ];
+    for t in 0..=max_time {
+        for d in 0..=(t * t / 4 + 1) {
+            let race = (t, d);
+            let count = brute_force(&race);
+            let big = solve_big(&BigUint::from(t), &BigUint::from(d));
+            if solve(&race) != count || big != BigUint::from(count) {
+                return Err(format!("race {:?}: expected {} ways", race, count));
+            }
+            for model in models.iter() {
+                let wins = (0..=t).filter(|&h| model.distance(t, h) > d as u128);
+                let expected = match (wins.clone().min(), wins.max()) {
+                    (Some(lo), Some(hi)) => Some((lo, hi)),
+                    _ => None,
+                };
+                if model.winning_interval(&race) != expected
+                    || search_interval(model.as_ref(), &race) != expected
+                {
+                    return Err(format!(
+                        "race {:?}: expected to win in {:?}",
+                        race, expected
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
     let input = fs::read_to_string("input.txt")?;
-    let races = parse_races(&input);
-    let ans1: i64 = races.iter().map(|r| solve(r)).product();
-    println!("{}", ans1);
+    match args.as_slice() {
+        [] => {
+            let races = parse_races(&input);
+            let ans1: u64 = races.iter().map(solve).product();
+            println!("{}", ans1);
+
+            let (t, d) = parse_big_race(&input);
+            match (t.to_u64(), d.to_u64()) {
+                (Some(t), Some(d)) => println!("{}", solve(&(t, d))),
+                _ => println!("{}", solve_big(&t, &d)),
+            }
+        }
+        [model] if model.starts_with("--model=") => {
+            let model = model
+                .strip_prefix("--model=")
+                .and_then(parse_model)
+                .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "bad model"))?;
+            let races = parse_races(&input);
+            let ans1: u64 = races
+                .iter()
+                .map(|r| count_wins(model.as_ref(), r))
+                .product();
+            println!("{}", ans1);
 
-    let big_race = parse_big_race(&input);
-    println!("{}", solve(&big_race));
+            let (t, d) = parse_big_race(&input);
+            let big_race = t.to_u64().zip(d.to_u64()).ok_or(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "race too long for the boat models",
+            ))?;
+            println!("{}", count_wins(model.as_ref(), &big_race));
+        }
+        _ => {
+            let usage = "usage: day6 [--model=charge:K|capped:K:MAX|warmup:K:D]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let input = fs::read_to_string("test.txt").unwrap();
+    let races = parse_races(&input);
+    assert_eq!(races.iter().map(solve).product::<u64>(), 288);
+    let (t, d) = parse_big_race(&input);
+    assert_eq!(solve_big(&t, &d), BigUint::from(71503_u32));
+
+    assert_eq!(check(60), Ok(()));
+}
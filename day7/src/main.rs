@@ -1,159 +1,324 @@
-use itertools::Itertools;
-use std::cmp::Ordering;
-use std::fs::File;
-use std::io::{self, prelude::*, BufReader};
-use std::iter::zip;
-
-type Hand = [i32; 5];
-
-#[derive(Eq, PartialEq, Ord, PartialOrd, Debug)]
-enum Kind {
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeOfAKind,
-    FullHouse,
-    FourOfAKind,
-    FiveOfAKind,
-}
-
-fn parse_line(s: &str, use_jokers: bool) -> Option<(Hand, usize)> {
-    let (hand_str, bid_str) = s.split_once(' ')?;
-    let hand = parse_hand(hand_str, use_jokers);
-    let bid: usize = bid_str.parse().unwrap_or(0);
-    Some((hand, bid))
-}
-
-fn parse_hand(s: &str, use_jokers: bool) -> Hand {
-    s.chars()
-        .filter_map(|c| match c {
-            'J' => Some(if use_jokers { 1 } else { 11 }),
-            '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => Some(c as i32 - 0x30),
-            'T' => Some(10),
-            'Q' => Some(12),
-            'K' => Some(13),
-            'A' => Some(14),
-            _ => None,
-        })
-        .collect::<Vec<i32>>()
-        .try_into()
-        .unwrap()
-}
-
-fn improve_hand(kind: Kind, num_jokers: usize) -> Kind {
-    if num_jokers == 1 {
-        match kind {
-            Kind::HighCard => Kind::OnePair,
-            Kind::OnePair => Kind::ThreeOfAKind,
-            Kind::TwoPair => Kind::FullHouse,
-            Kind::ThreeOfAKind | Kind::FullHouse => Kind::FourOfAKind,
-            Kind::FourOfAKind => Kind::FiveOfAKind,
-            _ => kind,
-        }
-    } else if num_jokers == 2 {
-        match kind {
-            Kind::HighCard => Kind::ThreeOfAKind,
-            Kind::OnePair => Kind::FourOfAKind,
-            Kind::ThreeOfAKind => Kind::FiveOfAKind,
-            _ => kind,
+use std::env;
+use std::fs;
+use std::io;
+
+// A kind of hand, made by having groups of at least these many equal cards.
+#[derive(Debug, Clone)]
+struct Category {
+    name: String,
+    groups: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Rules {
+    hand_size: usize,
+    // the cards from weakest to strongest
+    order: Vec<char>,
+    // cards that stand in for whichever card makes the best kind
+    wild: Vec<char>,
+    // the kinds of hand from weakest to strongest
+    categories: Vec<Category>,
+}
+
+impl Rules {
+    fn standard() -> Rules {
+        let categories = [
+            ("high card", vec![]),
+            ("one pair", vec![2]),
+            ("two pair", vec![2, 2]),
+            ("three of a kind", vec![3]),
+            ("full house", vec![3, 2]),
+            ("four of a kind", vec![4]),
+            ("five of a kind", vec![5]),
+        ];
+        Rules {
+            hand_size: 5,
+            order: "23456789TJQKA".chars().collect(),
+            wild: Vec::new(),
+            categories: categories
+                .into_iter()
+                .map(|(name, groups)| Category {
+                    name: name.to_string(),
+                    groups,
+                })
+                .collect(),
         }
-    } else if num_jokers == 3 {
-        match kind {
-            Kind::HighCard => Kind::FourOfAKind,
-            Kind::OnePair => Kind::FiveOfAKind,
-            _ => kind,
+    }
+
+    fn jokers() -> Rules {
+        Rules {
+            order: "J23456789TQKA".chars().collect(),
+            wild: vec!['J'],
+            ..Rules::standard()
         }
-    } else if num_jokers == 4 {
-        Kind::FiveOfAKind
-    } else {
-        kind
     }
-}
 
-fn hand_kind(hand: &Hand, use_jokers: bool) -> Kind {
-    let counts = hand.iter().sorted().dedup_with_count();
-    let mut jokers = 0;
-    let mut pairs = 0;
-    let mut threes = 0;
-    let mut fours = 0;
-    for (count, &card) in counts {
-        if card == 1 {
-            jokers += count;
-            continue;
+    fn bits_per_card(&self) -> u32 {
+        usize::BITS - self.order.len().saturating_sub(1).leading_zeros()
+    }
+
+    // Check that the kind and cards of a hand fit in its key.
+    fn validate(&self) -> Result<(), String> {
+        let kind_bits = usize::BITS - self.categories.len().saturating_sub(1).leading_zeros();
+        if self.order.is_empty() || self.categories.is_empty() {
+            return Err("no cards or no categories".to_string());
         }
-        match count {
-            5 => {
-                return Kind::FiveOfAKind;
-            }
-            4 => {
-                fours += 1;
-            }
-            3 => {
-                threes += 1;
-            }
-            2 => {
-                pairs += 1;
-            }
-            _ => {}
+        if self.hand_size as u32 * self.bits_per_card() + kind_bits > u64::BITS {
+            return Err(format!("hands of {} cards are too big", self.hand_size));
         }
+        Ok(())
     }
-    let kind = if fours == 1 {
-        Kind::FourOfAKind
-    } else if pairs == 2 {
-        Kind::TwoPair
-    } else if pairs == 1 && threes == 1 {
-        Kind::FullHouse
-    } else if threes == 1 {
-        Kind::ThreeOfAKind
-    } else if pairs == 1 {
-        Kind::OnePair
-    } else {
-        Kind::HighCard
-    };
+}
+
+// Hands order by their key, so sorting never recomputes the kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Hand {
+    // the kind in the top bits, then the rank of each card in order
+    key: u64,
+}
 
-    if use_jokers {
-        improve_hand(kind, jokers)
-    } else {
-        kind
+impl Hand {
+    fn new(cards: &[usize], rules: &Rules) -> Hand {
+        let kind = hand_kind(cards, rules, true) as u64;
+        let bits = rules.bits_per_card();
+        let key = cards.iter().fold(kind, |key, &c| (key << bits) | c as u64);
+        Hand { key }
     }
 }
 
-fn cmp_hands(h1: &Hand, h2: &Hand, use_jokers: bool) -> Ordering {
-    let t1 = hand_kind(h1, use_jokers);
-    let t2 = hand_kind(h2, use_jokers);
-    if t1 != t2 {
-        t1.cmp(&t2)
-    } else {
-        for (n1, n2) in zip(h1.iter(), h2.iter()) {
-            let ord = n1.cmp(n2);
-            if ord != Ordering::Equal {
-                return ord;
-            }
-        }
-        Ordering::Equal
+// the cards of the hand and its bid
+fn parse_line(s: &str, rules: &Rules) -> Result<(Vec<usize>, usize), String> {
+    let (hand_str, bid_str) = s.split_once(' ').ok_or("no bid")?;
+    let cards = parse_hand(hand_str, rules)?;
+    let bid: usize = bid_str
+        .parse()
+        .map_err(|_| format!("bad bid {:?}", bid_str))?;
+    Ok((cards, bid))
+}
+
+// the rank of each card
+fn parse_hand(s: &str, rules: &Rules) -> Result<Vec<usize>, String> {
+    let cards: Vec<usize> = s
+        .chars()
+        .map(|c| {
+            rules
+                .order
+                .iter()
+                .position(|&o| o == c)
+                .ok_or(format!("bad card {:?}", c))
+        })
+        .collect::<Result<_, _>>()?;
+    if cards.len() != rules.hand_size {
+        return Err(format!(
+            "{} cards in a hand, not {}",
+            cards.len(),
+            rules.hand_size
+        ));
     }
+    Ok(cards)
 }
 
-fn solve(hands_scores: &Vec<(Hand, usize)>, use_jokers: bool) -> usize {
-    let sorted_scores = hands_scores
+// every hand in the input with its bid, skipping blank lines
+fn parse_hands(input: &str, rules: &Rules) -> Result<Vec<(Vec<usize>, usize)>, String> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| parse_line(l, rules).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+// How many wild cards it takes to make the groups of a category, given the
+// sizes of the groups of equal cards from largest to smallest. Filling the
+// largest groups first is never worse.
+fn wilds_needed(cat: &Category, counts: &[usize]) -> usize {
+    cat.groups
         .iter()
-        .sorted_by(|t1, t2| cmp_hands(&t1.0, &t2.0, use_jokers))
-        .map(|t| t.1);
-    sorted_scores
         .enumerate()
-        .fold(0, |acc, (i, score)| acc + (i + 1) * score)
+        .map(|(i, &g)| g.saturating_sub(counts.get(i).copied().unwrap_or(0)))
+        .sum()
 }
 
-fn main() -> io::Result<()> {
-    //let file = File::open("test.txt")?;
-    let file = File::open("input.txt")?;
-    let reader = BufReader::new(file);
-    let use_jokers = true;
-    let hands_scores: Vec<(Hand, usize)> = reader
-        .lines()
-        .filter_map(|l| parse_line(&l.unwrap(), use_jokers))
+// the index of the best category the hand makes, optionally treating wild
+// cards as plain ones
+fn hand_kind(cards: &[usize], rules: &Rules, use_wild: bool) -> usize {
+    let mut counts = vec![0; rules.order.len()];
+    let mut wilds = 0;
+    for &c in cards {
+        if use_wild && rules.wild.contains(&rules.order[c]) {
+            wilds += 1;
+        } else {
+            counts[c] += 1;
+        }
+    }
+    counts.sort_unstable_by(|a, b| b.cmp(a));
+    rules
+        .categories
+        .iter()
+        .rposition(|cat| wilds_needed(cat, &counts) <= wilds)
+        .unwrap_or(0)
+}
+
+// The indices of the hands from weakest to strongest. The sort is stable, so
+// hands that tie keep their order in the input.
+fn ranking(hands: &[Hand]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..hands.len()).collect();
+    order.sort_by_key(|&i| hands[i]);
+    order
+}
+
+// whether the ith hand in rank order is equal to a neighbour, so its rank
+// among them is arbitrary
+fn is_tied(ranked: &[Hand], i: usize) -> bool {
+    (i > 0 && ranked[i - 1] == ranked[i]) || ranked.get(i + 1) == Some(&ranked[i])
+}
+
+fn solve(hands_scores: &[(Hand, usize)]) -> usize {
+    let hands: Vec<Hand> = hands_scores.iter().map(|&(hand, _)| hand).collect();
+    ranking(&hands)
+        .into_iter()
+        .enumerate()
+        .fold(0, |acc, (i, j)| acc + (i + 1) * hands_scores[j].1)
+}
+
+// List every hand by rank with its kinds, bid and winnings, flagging hands
+// that tie exactly with a neighbour, since their ranks are arbitrary.
+fn report(hands: &[(Vec<usize>, usize)], rules: &Rules) {
+    let keys: Vec<Hand> = hands
+        .iter()
+        .map(|(cards, _)| Hand::new(cards, rules))
         .collect();
-    println!("{:?}", solve(&hands_scores, use_jokers));
+    let order = ranking(&keys);
+    let ranked: Vec<Hand> = order.iter().map(|&i| keys[i]).collect();
+
+    let mut total = 0;
+    for (i, &j) in order.iter().enumerate() {
+        let (cards, bid) = (&hands[j].0, hands[j].1);
+        let rank = i + 1;
+        let base = &rules.categories[hand_kind(cards, rules, false)].name;
+        let kind = &rules.categories[hand_kind(cards, rules, true)].name;
+        let cards: String = cards.iter().map(|&c| rules.order[c]).collect();
+        let tied = is_tied(&ranked, i);
+        total += rank * bid;
+        println!(
+            "{:>6} {} {:<15} {:<15} {:>6} {:>10}{}",
+            rank,
+            cards,
+            base,
+            kind,
+            bid,
+            rank * bid,
+            if tied { " tie" } else { "" }
+        );
+    }
+    println!("total {}", total);
+}
+
+fn main() -> io::Result<()> {
+    let input = fs::read_to_string("input.txt")?;
+    let read_hands = |rules: &Rules| -> io::Result<Vec<(Vec<usize>, usize)>> {
+        rules
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        parse_hands(&input, rules).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {
+            for rules in [Rules::standard(), Rules::jokers()] {
+                let hands_scores: Vec<(Hand, usize)> = read_hands(&rules)?
+                    .iter()
+                    .map(|(cards, bid)| (Hand::new(cards, &rules), *bid))
+                    .collect();
+                println!("{:?}", solve(&hands_scores));
+            }
+        }
+        [flag, name] if flag == "--report" && (name == "standard" || name == "jokers") => {
+            let rules = if name == "jokers" {
+                Rules::jokers()
+            } else {
+                Rules::standard()
+            };
+            report(&read_hands(&rules)?, &rules);
+        }
+        _ => {
+            let usage = "usage: day7 [--report standard|jokers]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let rules = Rules::standard();
+    let hand = |s: &str| Hand::new(&parse_hand(s, &rules).unwrap(), &rules);
+    // the kind beats the cards
+    assert!(hand("22223") > hand("AAKKQ"));
+    assert!(hand("AAKKQ") > hand("AKQJT"));
+    // then the cards compare from the left
+    assert!(hand("33332") > hand("2AAAA"));
+    assert!(hand("KK677") > hand("KTJJT"));
+    assert!(hand("QQQJA") > hand("QQQ2A"));
+    assert_eq!(hand("T55J5"), hand("T55J5"));
+
+    let input = fs::read_to_string("test.txt").unwrap();
+    let total = |rules: &Rules| {
+        let hands_scores: Vec<(Hand, usize)> = parse_hands(&input, rules)
+            .unwrap()
+            .into_iter()
+            .map(|(cards, bid)| (Hand::new(&cards, rules), bid))
+            .collect();
+        solve(&hands_scores)
+    };
+    assert_eq!(total(&Rules::standard()), 6440);
+    assert_eq!(total(&Rules::jokers()), 5905);
+
+    // two jokers could make a full house, but four of a kind is better
+    let jokers = Rules::jokers();
+    let cards = parse_hand("JJKKQ", &jokers).unwrap();
+    assert_eq!(
+        jokers.categories[hand_kind(&cards, &jokers, true)].name,
+        "four of a kind"
+    );
+    let cards = parse_hand("JKKQQ", &jokers).unwrap();
+    assert_eq!(
+        jokers.categories[hand_kind(&cards, &jokers, true)].name,
+        "full house"
+    );
+    assert_eq!(
+        jokers.categories[hand_kind(&cards, &jokers, false)].name,
+        "two pair"
+    );
+    // JJKKQ has groups of 2 and 1 besides the jokers
+    let counts = [2, 1];
+    assert_eq!(wilds_needed(&jokers.categories[4], &counts), 2);
+    assert_eq!(wilds_needed(&jokers.categories[5], &counts), 2);
+    assert_eq!(wilds_needed(&jokers.categories[6], &counts), 3);
+
+    assert_eq!(Rules::standard().validate(), Ok(()));
+    let big = Rules {
+        hand_size: 20,
+        ..Rules::standard()
+    };
+    assert!(big.validate().is_err());
+
+    // equal hands rank in input order, the same in the report as in the total
+    let hands = [hand("KK677"), hand("32T3K"), hand("KK677"), hand("QQQJA")];
+    assert_eq!(ranking(&hands), [1, 0, 2, 3]);
+    let ranked: Vec<Hand> = ranking(&hands).into_iter().map(|i| hands[i]).collect();
+    let ties: Vec<bool> = (0..ranked.len()).map(|i| is_tied(&ranked, i)).collect();
+    assert_eq!(ties, [false, true, true, false]);
+    let bids = [10, 1, 100, 1000];
+    let hands_scores: Vec<(Hand, usize)> = hands.iter().copied().zip(bids).collect();
+    assert_eq!(solve(&hands_scores), 1 + 2 * 10 + 3 * 100 + 4 * 1000);
+
+    assert_eq!(
+        parse_hands("32T3K 765\n32T3X 1\n", &Rules::standard()),
+        Err("line 2: bad card 'X'".to_string())
+    );
+    assert!(parse_hands("32T3 765", &Rules::standard()).is_err());
+    assert!(parse_hands("32T3K bid", &Rules::standard()).is_err());
+}
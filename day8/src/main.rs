@@ -1,6 +1,7 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::io;
 
@@ -10,23 +11,63 @@ enum Dir {
     Right,
 }
 
-type Graph = HashMap<String, (String, String)>;
+impl Dir {
+    fn as_char(&self) -> char {
+        match self {
+            Dir::Left => 'L',
+            Dir::Right => 'R',
+        }
+    }
+}
+
+// Nodes are interned as indices into `names`.
+#[derive(Debug)]
+struct Graph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    // the left and right neighbours of each node
+    edges: Vec<(usize, usize)>,
+}
 
 lazy_static! {
     static ref LINE_REGEX: Regex =
-        Regex::new(r"^([A-Z]{3}) = \(([A-Z]{3}), ([A-Z]{3})\)$").unwrap();
+        Regex::new(r"^([0-9A-Za-z]+) = \(([0-9A-Za-z]+), ([0-9A-Za-z]+)\)$").unwrap();
+}
+
+fn parse_line(l: &str) -> Option<(&str, (&str, &str))> {
+    let (_, [n1, n2, n3]) = LINE_REGEX.captures(l)?.extract();
+    Some((n1, (n2, n3)))
 }
 
-fn parse_line(l: &str) -> Option<(String, (String, String))> {
-    let captures = LINE_REGEX.captures_iter(l).map(|caps| caps.extract());
-    for (_, [n1, n2, n3]) in captures {
-        return Some((n1.to_string(), (n2.to_string(), n3.to_string())));
+fn parse_graph(s: &str) -> Result<Graph, String> {
+    let mut lines: Vec<(&str, (&str, &str))> = Vec::new();
+    for l in s.lines().filter(|l| !l.trim().is_empty()) {
+        lines.push(parse_line(l.trim()).ok_or(format!("bad node: {:?}", l))?);
     }
-    None
+
+    let mut names: Vec<String> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for &(name, _) in lines.iter() {
+        if ids.insert(name.to_string(), names.len()).is_some() {
+            return Err(format!("node {} is defined twice", name));
+        }
+        names.push(name.to_string());
+    }
+
+    let lookup = |from: &str, to: &str| {
+        ids.get(to)
+            .copied()
+            .ok_or(format!("node {} refers to undefined node {}", from, to))
+    };
+    let edges = lines
+        .iter()
+        .map(|&(name, (left, right))| Ok((lookup(name, left)?, lookup(name, right)?)))
+        .collect::<Result<_, String>>()?;
+    Ok(Graph { names, ids, edges })
 }
 
-fn parse(s: &str) -> Option<(Vec<Dir>, Graph)> {
-    let (dir_str, graph_str) = s.split_once("\n\n")?;
+fn parse(s: &str) -> Result<(Vec<Dir>, Graph), String> {
+    let (dir_str, graph_str) = s.split_once("\n\n").ok_or("missing network")?;
     let dirs: Vec<Dir> = dir_str
         .chars()
         .filter_map(|c| match c {
@@ -35,66 +76,329 @@ fn parse(s: &str) -> Option<(Vec<Dir>, Graph)> {
             _ => None,
         })
         .collect();
-    let graph: Graph = graph_str.lines().filter_map(|l| parse_line(l)).collect();
-
-    Some((dirs, graph))
+    if dirs.is_empty() {
+        return Err("no instructions".to_string());
+    }
+    Ok((dirs, parse_graph(graph_str)?))
 }
 
-fn gcd_two(mut n: u64, mut m: u64) -> u64 {
+fn gcd(mut n: u128, mut m: u128) -> u128 {
     while m != 0 {
-        if m < n {
-            std::mem::swap(&mut n, &mut m);
-        }
-        m = m % n;
+        (n, m) = (m, n % m);
     }
     n
 }
 
-fn lcm_two(n: u64, m: u64) -> u64 {
-    n * (m / gcd_two(n, m))
+// the inverse of n mod m, for n and m coprime
+fn mod_inverse(n: u128, m: u128) -> u128 {
+    let (mut old_r, mut r) = (n as i128, m as i128);
+    let (mut old_s, mut s) = (1_i128, 0_i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    old_s.rem_euclid(m as i128) as u128
+}
+
+// Combine x = a1 (mod m1) and x = a2 (mod m2) into a single congruence, or
+// None if they have no common solution.
+fn crt((a1, m1): (u128, u128), (a2, m2): (u128, u128)) -> Result<Option<(u128, u128)>, String> {
+    let overflow = || format!("cycles of length {} and {} overflow", m1, m2);
+    let g = gcd(m1, m2);
+    let diff = (a2 + m2 - a1 % m2) % m2;
+    if diff % g != 0 {
+        return Ok(None);
+    }
+    let lcm = (m1 / g).checked_mul(m2).ok_or_else(overflow)?;
+    let m = m2 / g;
+    let k = (diff / g)
+        .checked_mul(mod_inverse((m1 / g) % m, m))
+        .ok_or_else(overflow)?
+        % m;
+    Ok(Some(((a1 + m1 * k) % lcm, lcm)))
+}
+
+// When a ghost stands on an end node: at some times before it enters the
+// cycle of its (node, instruction) states, then periodically.
+#[derive(Debug, Clone)]
+struct Hits {
+    // when the cycle is entered, and its length
+    start: u64,
+    period: u64,
+    // end times before `start`
+    early: Vec<u64>,
+    // end times in the first pass through the cycle
+    cycle: Vec<u64>,
+}
+
+impl Hits {
+    fn contains(&self, t: u64) -> bool {
+        if t < self.start {
+            self.early.binary_search(&t).is_ok()
+        } else {
+            let t = self.start + (t - self.start) % self.period;
+            self.cycle.binary_search(&t).is_ok()
+        }
+    }
+}
+
+fn step(g: &Graph, node: usize, dir: &Dir) -> usize {
+    let (left, right) = g.edges[node];
+    match dir {
+        Dir::Left => left,
+        Dir::Right => right,
+    }
+}
+
+fn ghost_hits(g: &Graph, dirs: &[Dir], start: usize, is_end: impl Fn(usize) -> bool) -> Hits {
+    let mut seen: HashMap<(usize, usize), u64> = HashMap::new();
+    let mut ends: Vec<u64> = Vec::new();
+    let mut node = start;
+    let mut t: u64 = 0;
+    let cycle_start = loop {
+        let i = (t % dirs.len() as u64) as usize;
+        if let Some(&first) = seen.get(&(node, i)) {
+            break first;
+        }
+        seen.insert((node, i), t);
+        if is_end(node) {
+            ends.push(t);
+        }
+        node = step(g, node, &dirs[i]);
+        t += 1;
+    };
+    let (early, cycle) = ends.into_iter().partition(|&e| e < cycle_start);
+    Hits {
+        start: cycle_start,
+        period: t - cycle_start,
+        early,
+        cycle,
+    }
+}
+
+// The first time all ghosts stand on end nodes together, if ever.
+fn earliest_meeting(ghosts: &[Hits]) -> Result<Option<u64>, String> {
+    if ghosts.is_empty() {
+        return Ok(None);
+    }
+    // until the last ghost reaches its cycle, a meeting is one of its early times
+    let early = ghosts
+        .iter()
+        .flat_map(|h| h.early.iter().copied())
+        .filter(|&t| ghosts.iter().all(|h| h.contains(t)))
+        .min();
+    if early.is_some() {
+        return Ok(early);
+    }
+
+    // after that, every combination of cycle times gives a congruence
+    let lead_in = ghosts.iter().map(|h| h.start).max().unwrap_or(0) as u128;
+    let mut congruences: Vec<(u128, u128)> = vec![(0, 1)];
+    for h in ghosts {
+        let period = h.period as u128;
+        let mut next = Vec::new();
+        for &c in congruences.iter() {
+            for &t in h.cycle.iter() {
+                next.extend(crt(c, (t as u128 % period, period))?);
+            }
+        }
+        next.sort();
+        next.dedup();
+        congruences = next;
+    }
+    congruences
+        .into_iter()
+        .map(|(x, m)| {
+            let t = if x >= lead_in {
+                x
+            } else {
+                x + (lead_in - x).div_ceil(m) * m
+            };
+            u64::try_from(t).map_err(|_| format!("meeting at {} overflows", t))
+        })
+        .min()
+        .transpose()
 }
 
-// Run search from start until stop_fn(node) is true
-fn run(g: &Graph, dirs: &Vec<Dir>, start: &String, stop_fn: fn(&String) -> bool) -> u64 {
+// Run search from start until stop_fn(node) is true. With a trace limit, print
+// each step taken and give up after that many steps.
+fn run(
+    g: &Graph,
+    dirs: &[Dir],
+    start: usize,
+    stop_fn: impl Fn(usize) -> bool,
+    trace: Option<u64>,
+) -> u64 {
     let mut steps = 0;
     let mut state = start;
     for dir in dirs.iter().cycle() {
         if stop_fn(state) {
+            if trace.is_some() {
+                println!("{:>8} {}", steps, g.names[state]);
+            }
             break;
         }
-        let (left, right) = g.get(state).unwrap();
-        state = match dir {
-            Dir::Left => left,
-            Dir::Right => right,
-        };
+        if trace.is_some_and(|limit| steps >= limit) {
+            println!("stopped after {} steps", steps);
+            break;
+        }
+        let next = step(g, state, dir);
+        if trace.is_some() {
+            println!(
+                "{:>8} {} {} {}",
+                steps,
+                g.names[state],
+                dir.as_char(),
+                g.names[next]
+            );
+        }
+        state = next;
         steps += 1;
     }
     steps
 }
 
-fn solve1(g: &Graph, dirs: &Vec<Dir>) -> u64 {
-    let start = String::from("AAA");
-    return run(g, dirs, &start, |s| s == "ZZZ");
+// Graphviz source for the network, with start nodes in green, end nodes in
+// red, and edges labelled by the direction taking them.
+fn to_dot(g: &Graph) -> String {
+    let mut dot = String::from("digraph network {\n");
+    for (n, name) in g.names.iter().enumerate() {
+        if name.ends_with('A') {
+            dot += &format!("    \"{}\" [style=filled, fillcolor=palegreen];\n", name);
+        } else if name.ends_with('Z') {
+            dot += &format!("    \"{}\" [style=filled, fillcolor=salmon];\n", name);
+        }
+        let (left, right) = g.edges[n];
+        let edges = if left == right {
+            vec![(left, "L/R")]
+        } else {
+            vec![(left, "L"), (right, "R")]
+        };
+        for (to, label) in edges {
+            dot += &format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                name, g.names[to], label
+            );
+        }
+    }
+    dot += "}\n";
+    dot
+}
+
+fn solve1(g: &Graph, dirs: &[Dir]) -> Result<u64, String> {
+    let start = *g.ids.get("AAA").ok_or("no node AAA")?;
+    let end = *g.ids.get("ZZZ").ok_or("no node ZZZ")?;
+    Ok(run(g, dirs, start, |n| n == end, None))
 }
 
-fn solve2(g: &Graph, dirs: &Vec<Dir>) -> u64 {
-    // this works if the paths from each starting node are disjoint cycles
-    // with exactly one 'Z' node on each cycle.
-    let starts = g.keys().filter(|k| k.ends_with("A"));
-    let steps: Vec<u64> = starts
-        .map(|start| run(g, dirs, start, |s| s.ends_with("Z")))
+fn solve2(g: &Graph, dirs: &[Dir]) -> Result<Option<u64>, String> {
+    let is_end = |n: usize| g.names[n].ends_with('Z');
+    let ghosts: Vec<Hits> = (0..g.names.len())
+        .filter(|&n| g.names[n].ends_with('A'))
+        .map(|start| ghost_hits(g, dirs, start, is_end))
         .collect();
-    let init = steps[0];
-    let lcm = steps.into_iter().fold(init, |acc, x| lcm_two(acc, x));
-    lcm
+    earliest_meeting(&ghosts)
 }
 
 fn main() -> io::Result<()> {
     let input = fs::read_to_string("input.txt")?;
-    let (dirs, graph) = parse(&input).unwrap();
+    let (dirs, graph) = parse(&input).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {}
+        [flag] if flag == "--dot" => {
+            print!("{}", to_dot(&graph));
+            return Ok(());
+        }
+        // follow the path from <start> to an end node, for at most <limit> steps
+        [flag, start, rest @ ..] if flag == "--trace" && rest.len() <= 1 => {
+            let start = *graph.ids.get(start).ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no node {}", start),
+            ))?;
+            let limit: u64 = match rest.first() {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad limit"))?,
+                None => 1000,
+            };
+            run(
+                &graph,
+                &dirs,
+                start,
+                |n| graph.names[n].ends_with('Z'),
+                Some(limit),
+            );
+            return Ok(());
+        }
+        _ => {
+            let usage = "usage: day8 [--dot | --trace <start> [limit]]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
-    println!("{:?}", solve1(&graph, &dirs));
-    println!("{:?}", solve2(&graph, &dirs));
+    match solve1(&graph, &dirs) {
+        Ok(steps) => println!("{:?}", steps),
+        Err(e) => println!("{}", e),
+    }
+    match solve2(&graph, &dirs) {
+        Ok(Some(steps)) => println!("{:?}", steps),
+        Ok(None) => println!("the ghosts never meet"),
+        Err(e) => println!("{}", e),
+    }
 
     Ok(())
 }
+
+#[test]
+fn test() {
+    let input = fs::read_to_string("test.txt").unwrap();
+    let (dirs, g) = parse(&input).unwrap();
+    assert_eq!(dirs.len(), 2);
+    assert_eq!(g.names.len(), 8);
+    let a = g.ids["11A"];
+    assert_eq!(g.names[g.edges[a].0], "11B");
+
+    assert!(parse_graph("AAA = (AAA, AAA)\nAAA = (AAA, AAA)").is_err());
+    assert!(parse_graph("AAA = (BBB, CCC)\nBBB = (AAA, AAA)").is_err());
+    assert!(parse_graph("AAA = (AAA AAA)").is_err());
+    assert!(parse("AAA = (AAA, AAA)").is_err());
+
+    assert_eq!(solve2(&g, &dirs), Ok(Some(6)));
+
+    assert_eq!(mod_inverse(3, 7), 5);
+    // 10 is 2 mod 4 and 4 mod 6
+    assert_eq!(crt((2, 4), (4, 6)), Ok(Some((10, 12))));
+    // odd and even
+    assert_eq!(crt((1, 4), (2, 6)), Ok(None));
+
+    // an end at time 1, then a cycle GB -> HZ -> GC from time 2
+    let g = parse_graph(
+        "GSA = (GZZ, GZZ)\nGZZ = (GB, GB)\nGB = (HZ, HZ)\nHZ = (GC, GC)\nGC = (GB, GB)",
+    )
+    .unwrap();
+    let hits = ghost_hits(&g, &[Dir::Left], g.ids["GSA"], |n| {
+        g.names[n].ends_with('Z')
+    });
+    assert_eq!((hits.start, hits.period), (2, 3));
+    assert_eq!(
+        (hits.early.as_slice(), hits.cycle.as_slice()),
+        (&[1][..], &[3][..])
+    );
+    // a ghost on an end node at time t mod period
+    let periodic = |period: u64, t: u64| Hits {
+        start: 0,
+        period,
+        early: vec![],
+        cycle: vec![t],
+    };
+    assert_eq!(
+        earliest_meeting(&[hits.clone(), periodic(2, 1)]),
+        Ok(Some(1))
+    );
+    assert_eq!(earliest_meeting(&[hits, periodic(4, 2)]), Ok(Some(6)));
+    let (odd, even) = (periodic(2, 1), periodic(4, 2));
+    assert_eq!(earliest_meeting(&[odd, even]), Ok(None));
+}
@@ -1,51 +1,227 @@
-use std::io::{self, prelude::*, BufReader};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use std::env;
+use std::fmt;
 use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
 
-fn parse_line(s: &str) -> Vec<i32> {
+fn parse_line(s: &str) -> Vec<i64> {
     s.split(' ').filter_map(|x| x.parse().ok()).collect()
 }
 
-fn diff(seq: &Vec<i32>) -> Vec<i32> {
-    let mut out = Vec::new();
-    let len = seq.len();
-    for i in 1..len {
-        out.push(seq[i] - seq[i-1])
-    }
-    out
+fn diff(seq: &[BigInt]) -> Vec<BigInt> {
+    seq.windows(2).map(|w| &w[1] - &w[0]).collect()
 }
 
-fn diff_until_const(seq: &Vec<i32>) -> Vec<Vec<i32>> {
-    let mut diffs : Vec<Vec<i32>> = vec![seq.clone()];
-    for i in 0..seq.len() {
-        let diff_seq = diff(&diffs[i]);
-        if diff_seq.iter().all(|&x| x == 0) {
-            break;
+// The rows of differences down to the last one that isn't all zeros, and
+// whether a row of zeros follows it. If the rows run out first, the sequence
+// isn't polynomial within its length.
+fn diff_until_const(seq: &[i64]) -> (Vec<Vec<BigInt>>, bool) {
+    let mut diffs: Vec<Vec<BigInt>> = vec![seq.iter().map(|&x| BigInt::from(x)).collect()];
+    loop {
+        let diff_seq = diff(&diffs[diffs.len() - 1]);
+        if diff_seq.is_empty() {
+            return (diffs, false);
+        }
+        if diff_seq.iter().all(|x| x.is_zero()) {
+            return (diffs, true);
         }
         diffs.push(diff_seq);
     }
-    diffs
 }
 
-fn next_value(seq: &Vec<i32>) -> i32 {
-    let diffs = diff_until_const(seq);
-    diffs.into_iter().rev().fold(0, |acc, v| acc + v.last().unwrap())
+// the degree of the sequence, or None if it isn't polynomial within its length
+fn degree(seq: &[i64]) -> Option<usize> {
+    let (diffs, is_poly) = diff_until_const(seq);
+    is_poly.then_some(diffs.len() - 1)
+}
+
+// Print the degree of every sequence, flagging those that aren't polynomial.
+fn report(seqs: &[Vec<i64>]) {
+    let mut flagged = 0;
+    for (i, seq) in seqs.iter().enumerate() {
+        match degree(seq) {
+            Some(d) => println!("{:>5} degree {}", i + 1, d),
+            None => {
+                println!("{:>5} not polynomial within {} values", i + 1, seq.len());
+                flagged += 1;
+            }
+        }
+    }
+    println!("{} of {} sequences not polynomial", flagged, seqs.len());
+}
+
+fn check_polynomial(seqs: &[Vec<i64>]) -> Result<(), String> {
+    match seqs.iter().position(|seq| degree(seq).is_none()) {
+        Some(i) => Err(format!(
+            "line {}: not polynomial within {} values",
+            i + 1,
+            seqs[i].len()
+        )),
+        None => Ok(()),
+    }
 }
 
-fn prev_value(seq: &Vec<i32>) -> i32 {
-    let diffs = diff_until_const(seq);
-    diffs.into_iter().rev().fold(0, |acc, v| v[0] - acc)
+// A polynomial in Newton forward-difference form,
+//   p(x) = sum_k coeffs[k] * C(x, k),
+// where coeffs[k] is the kth difference of the sequence at x = 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Newton {
+    coeffs: Vec<BigInt>,
+}
+
+impl Newton {
+    // The lowest degree polynomial through the sequence, with x = 0 at its
+    // start. A sequence that isn't polynomial gets the one of degree len - 1.
+    fn fit(seq: &[i64]) -> Newton {
+        let mut coeffs: Vec<BigInt> = diff_until_const(seq)
+            .0
+            .into_iter()
+            .filter_map(|d| d.into_iter().next())
+            .collect();
+        while coeffs.last().is_some_and(|c| c.is_zero()) {
+            coeffs.pop();
+        }
+        Newton { coeffs }
+    }
+
+    // Since C(x, k) = C(x, k - 1) * (x - k + 1) / k is an integer for any
+    // integer x, every term divides exactly.
+    fn eval(&self, x: &BigInt) -> BigInt {
+        let mut binom = BigInt::one();
+        let mut value = BigInt::zero();
+        for (k, c) in self.coeffs.iter().enumerate() {
+            if k > 0 {
+                binom = binom * (x - (k - 1)) / k;
+            }
+            value += c * &binom;
+        }
+        value
+    }
+
+    // coefficients of 1, x, x^2, ...
+    fn power_coeffs(&self) -> Vec<BigRational> {
+        let mut coeffs = vec![BigRational::zero(); self.coeffs.len()];
+        // x (x - 1) ... (x - k + 1) and k!
+        let mut falling: Vec<BigInt> = vec![BigInt::one()];
+        let mut fact = BigInt::one();
+        for (k, c) in self.coeffs.iter().enumerate() {
+            if k > 0 {
+                let mut next = vec![BigInt::zero(); falling.len() + 1];
+                for (i, f) in falling.iter().enumerate() {
+                    next[i + 1] += f;
+                    next[i] -= f * (k - 1);
+                }
+                falling = next;
+                fact *= k;
+            }
+            for (i, f) in falling.iter().enumerate() {
+                coeffs[i] += BigRational::new(c * f, fact.clone());
+            }
+        }
+        coeffs
+    }
+}
+
+// the polynomial in x, highest power first
+impl fmt::Display for Newton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<(usize, BigRational)> = self
+            .power_coeffs()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_zero())
+            .rev()
+            .collect();
+        if terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, (pow, c)) in terms.iter().enumerate() {
+            let sign = if c.is_negative() { "-" } else { "+" };
+            match i {
+                0 if c.is_negative() => write!(f, "-")?,
+                0 => {}
+                _ => write!(f, " {} ", sign)?,
+            }
+            let c = c.abs();
+            let var = match pow {
+                0 => String::new(),
+                1 => "x".to_string(),
+                _ => format!("x^{}", pow),
+            };
+            if *pow == 0 {
+                write!(f, "{}", c)?;
+            } else if c.is_one() {
+                write!(f, "{}", var)?;
+            } else {
+                write!(f, "{}*{}", c, var)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn next_value(seq: &[i64]) -> BigInt {
+    Newton::fit(seq).eval(&BigInt::from(seq.len()))
+}
+
+fn prev_value(seq: &[i64]) -> BigInt {
+    Newton::fit(seq).eval(&BigInt::from(-1))
 }
 
 fn main() -> io::Result<()> {
     let file = File::open("input.txt")?;
     let reader = BufReader::new(file);
 
-    let seqs : Vec<Vec<i32>> = reader.lines().map(|l| parse_line(&l.unwrap())).collect();
-    let ans1 : i32 = seqs.iter().map(|seq| next_value(seq)).sum();
-    println!("{}", ans1);
+    let seqs: Vec<Vec<i64>> = reader.lines().map(|l| parse_line(&l.unwrap())).collect();
+
+    let bad_arg =
+        |a: &String| io::Error::new(io::ErrorKind::InvalidInput, format!("bad index {}", a));
+    // with --strict, sequences that aren't polynomial are an error
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--strict") {
+        args.retain(|a| a != "--strict");
+        check_polynomial(&seqs).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    match args.as_slice() {
+        [] => {
+            let ans1: BigInt = seqs.iter().map(|seq| next_value(seq)).sum();
+            println!("{}", ans1);
 
-    let ans2 : i32 = seqs.iter().map(|seq| prev_value(seq)).sum();
-    println!("{}", ans2);
+            let ans2: BigInt = seqs.iter().map(|seq| prev_value(seq)).sum();
+            println!("{}", ans2);
+        }
+        [flag] if flag == "--report" => report(&seqs),
+        // each sequence's polynomial, with x = 0 at its first value
+        [flag] if flag == "--poly" => {
+            for seq in seqs.iter() {
+                println!("{}", Newton::fit(seq));
+            }
+        }
+        // the sum of the sequences at index x
+        [flag, x] if flag == "--at" => {
+            let x: BigInt = x.parse().map_err(|_| bad_arg(x))?;
+            let ans: BigInt = seqs.iter().map(|seq| Newton::fit(seq).eval(&x)).sum();
+            println!("{}", ans);
+        }
+        // each sequence at indices lo..=hi
+        [flag, lo, hi] if flag == "--range" => {
+            let lo: i64 = lo.parse().map_err(|_| bad_arg(lo))?;
+            let hi: i64 = hi.parse().map_err(|_| bad_arg(hi))?;
+            for seq in seqs.iter() {
+                let poly = Newton::fit(seq);
+                let values: Vec<String> = (lo..=hi)
+                    .map(|x| poly.eval(&BigInt::from(x)).to_string())
+                    .collect();
+                println!("{}", values.join(" "));
+            }
+        }
+        _ => {
+            let usage = "usage: day9 [--strict] [--report | --poly | --at <x> | --range <lo> <hi>]";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, usage));
+        }
+    }
 
     Ok(())
 }
@@ -54,14 +230,33 @@ fn main() -> io::Result<()> {
 fn test() {
     let file = File::open("test.txt").unwrap();
     let reader = BufReader::new(file);
-    let seqs : Vec<Vec<i32>> = reader.lines().map(|l| parse_line(&l.unwrap())).collect();
-    let mut next_vals = seqs.iter().map(|seq| next_value(&seq));
-    assert_eq!(next_vals.next(), Some(18));
-    assert_eq!(next_vals.next(), Some(28));
-    assert_eq!(next_vals.next(), Some(68));
-
-    let mut prev_vals = seqs.iter().map(|seq| prev_value(&seq));
-    assert_eq!(prev_vals.next(), Some(-3));
-    assert_eq!(prev_vals.next(), Some(0));
-    assert_eq!(prev_vals.next(), Some(5));
+    let seqs: Vec<Vec<i64>> = reader.lines().map(|l| parse_line(&l.unwrap())).collect();
+    let mut next_vals = seqs.iter().map(|seq| next_value(seq));
+    assert_eq!(next_vals.next(), Some(BigInt::from(18)));
+    assert_eq!(next_vals.next(), Some(BigInt::from(28)));
+    assert_eq!(next_vals.next(), Some(BigInt::from(68)));
+
+    let mut prev_vals = seqs.iter().map(|seq| prev_value(seq));
+    assert_eq!(prev_vals.next(), Some(BigInt::from(-3)));
+    assert_eq!(prev_vals.next(), Some(BigInt::from(0)));
+    assert_eq!(prev_vals.next(), Some(BigInt::from(5)));
+
+    let polys: Vec<String> = seqs
+        .iter()
+        .map(|seq| Newton::fit(seq).to_string())
+        .collect();
+    assert_eq!(
+        polys,
+        ["3*x", "1/2*x^2 + 3/2*x + 1", "1/3*x^3 - x^2 + 11/3*x + 10"]
+    );
+    let far = BigInt::from(10).pow(30);
+    assert_eq!(Newton::fit(&seqs[0]).eval(&far), far * 3);
+
+    let degrees: Vec<Option<usize>> = seqs.iter().map(|seq| degree(seq)).collect();
+    assert_eq!(degrees, [Some(1), Some(2), Some(3)]);
+    assert_eq!(check_polynomial(&seqs), Ok(()));
+    assert_eq!(degree(&[1, 2, 4, 8, 16]), None);
+    assert_eq!(degree(&[]), None);
+    assert_eq!(degree(&[7, 7]), Some(0));
+    assert!(check_polynomial(&[vec![1, 2, 4, 8]]).is_err());
 }